@@ -1,23 +1,249 @@
-use std::{sync::Arc, vec};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    marker::PhantomData,
+    sync::Arc,
+    vec,
+};
 use itertools::Itertools;
 
+use crate::circuit::{Circuit, Constraint, ConstraintLog, Lookup, Lookups, SVStruct, Table, TableId};
+
 use super::storage::{ReaderOf, Storage, TypedAddr, WriterOf};
 
 pub trait RTAdvice<S: Storage> {
     fn inputs(&self) -> Vec<S::RawAddr>;
     fn outputs(&self) -> Vec<S::RawAddr>;
     fn call(&self, storage: &mut S);
+
+    /// Whether the advice implements call_local, i.e. its values can be kept out of storage.
+    fn supports_local(&self) -> bool {
+        false
+    }
+
+    /// Calls the advice, reading and writing values kept by local there instead of storage. Advices which do not
+    /// support local values are called on storage, so none of their values may be kept.
+    fn call_local(&self, storage: &mut S, _local: &mut LocalValues<S>) {
+        self.call(storage)
+    }
+
+    /// Calls of registered advice kinds (see backend::registry) the advice consists of, in order of execution.
+    /// None if the advice is built from an anonymous closure, i.e. can not be serialised.
+    fn kinds(&self) -> Option<Vec<KindCall<S::RawAddr>>> {
+        None
+    }
+}
+
+/// Call of a registered advice kind, in a serialisable form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KindCall<A> {
+    pub name: &'static str,
+    pub params: Vec<String>,
+    pub inputs: Vec<A>,
+    pub outputs: Vec<A>,
+}
+
+/// Values which a fused advice (see middleend::fusion::FusedAdvice) passes between its parts without storing them.
+pub struct LocalValues<'a, S: Storage> {
+    kept: &'a HashSet<S::RawAddr>,
+    values: HashMap<S::RawAddr, Box<dyn Any>>,
+}
+
+impl<'a, S: Storage> LocalValues<'a, S> {
+    /// Values at kept addresses are stored locally, all others in storage.
+    pub fn new(kept: &'a HashSet<S::RawAddr>) -> Self {
+        LocalValues { kept, values: HashMap::new() }
+    }
+
+    /// Panics if a kept value is not computed yet.
+    pub fn get<'b, T: 'static>(&'b self, storage: &'b S, addr: &S::RawAddr) -> &'b T
+    where
+        S: ReaderOf<T>,
+    {
+        if !self.kept.contains(addr) {
+            return storage.get(addr);
+        }
+        self.values.get(addr)
+            .and_then(|value| value.downcast_ref())
+            .unwrap_or_else(|| panic!("local value {addr:?} is not computed"))
+    }
+
+    pub fn put<T: 'static>(&mut self, storage: &mut S, addr: &S::RawAddr, val: T)
+    where
+        S: WriterOf<T>,
+    {
+        if !self.kept.contains(addr) {
+            return storage.put(addr, val);
+        }
+        assert!(self.values.insert(*addr, Box::new(val)).is_none(), "local value {addr:?} is written twice");
+    }
+}
+
+/// Advice of an execution graph, with the namespace of the circuit it was recorded in.
+pub struct RTNode<S: Storage> {
+    pub advice: Box<dyn RTAdvice<S>>,
+    pub namespace: String,
 }
 
 pub struct RTGraph<S: Storage> {
     inputs: Vec<S::RawAddr>,
+    constants: Vec<S::RawAddr>,
     outputs: Vec<S::RawAddr>,
-    advices: Vec<Box<dyn RTAdvice<S>>>,
+    advices: Vec<RTNode<S>>,
+    /// Committed values, by commitment group.
+    groups: Vec<Vec<S::RawAddr>>,
+    /// Challenges, with the commitment group after which they are derived.
+    challenges: Vec<(usize, S::RawAddr)>,
+    /// Ends of stages in advices. Empty if the graph is not staged.
+    stages: Vec<usize>,
+}
+
+impl<S: Storage> RTGraph<S> {
+    pub fn new(
+        inputs: Vec<S::RawAddr>,
+        constants: Vec<S::RawAddr>,
+        outputs: Vec<S::RawAddr>,
+        advices: Vec<RTNode<S>>,
+        groups: Vec<Vec<S::RawAddr>>,
+        challenges: Vec<(usize, S::RawAddr)>,
+    ) -> Self {
+        RTGraph { inputs, constants, outputs, advices, groups, challenges, stages: vec![] }
+    }
+
+    /// Values which have to be provided externally.
+    pub fn inputs(&self) -> &Vec<S::RawAddr> {
+        &self.inputs
+    }
+
+    /// Constants which no advice computes. They are fixed by the circuit and written by the backend before
+    /// execution, like inputs.
+    pub fn constants(&self) -> &Vec<S::RawAddr> {
+        &self.constants
+    }
+
+    /// Values which have to be computed, i.e. signals read by the backend.
+    pub fn outputs(&self) -> &Vec<S::RawAddr> {
+        &self.outputs
+    }
+
+    pub fn advices(&self) -> &Vec<RTNode<S>> {
+        &self.advices
+    }
+
+    /// Unsafe. Direct access to advices, for middleend passes.
+    pub fn advices_mut(&mut self) -> &mut Vec<RTNode<S>> {
+        &mut self.advices
+    }
+
+    pub fn groups(&self) -> &Vec<Vec<S::RawAddr>> {
+        &self.groups
+    }
+
+    pub fn challenges(&self) -> &Vec<(usize, S::RawAddr)> {
+        &self.challenges
+    }
+
+    /// Ends of stages: advices of round r are advices[stages[r - 1]..stages[r]]. Round r only reads challenges
+    /// derived after groups before r, and completes groups up to r, so the backend can execute round r and then
+    /// fill challenges derived after group r. Empty if the graph is not staged.
+    pub fn stages(&self) -> &Vec<usize> {
+        &self.stages
+    }
+
+    /// Advices of the round. Panics if the graph is not staged.
+    pub fn stage(&self, round: usize) -> &[RTNode<S>] {
+        assert!(round < self.stages.len(), "graph has no round {round}");
+        let start = if round == 0 { 0 } else { self.stages[round - 1] };
+        &self.advices[start..self.stages[round]]
+    }
+
+    /// Unsafe. Sets ends of stages, for middleend passes. Should be reset by passes reordering advices.
+    pub fn _set_stages(&mut self, stages: Vec<usize>) {
+        self.stages = stages;
+    }
+
+    /// Absorbs the witness of a completed commitment group into the transcript and writes challenges derived after it.
+    /// Returns an error if the graph has no such group, leaving the transcript unchanged.
+    pub fn fill_challenges(&self, storage: &mut S, group: usize, transcript: &mut impl Transcript<S>) -> Result<(), UnknownGroup> {
+        let witness = self.groups.get(group).ok_or(UnknownGroup(group))?;
+        transcript.absorb_group(storage, group, witness);
+        for (after, addr) in self.challenges.iter() {
+            if *after == group {
+                transcript.squeeze_challenge(storage, addr);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Commitment group which the graph does not have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownGroup(pub usize);
+
+impl Display for UnknownGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "graph has no commitment group {}", self.0)
+    }
+}
+
+/// Source of verifier challenges in multi-round proof systems.
+pub trait Transcript<S: Storage> {
+    /// Absorbs committed values of a group.
+    fn absorb_group(&mut self, storage: &S, group: usize, witness: &[S::RawAddr]);
+    /// Derives a challenge from everything absorbed so far and writes it into storage.
+    fn squeeze_challenge(&mut self, storage: &mut S, addr: &S::RawAddr);
+}
+
+/// Error of GraphBackend::complete_group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupError<A> {
+    Unknown(UnknownGroup),
+    /// Committed value of the group which can not be computed, e.g. as it depends on a challenge not filled yet.
+    Incomplete { group: usize, addr: A },
+}
+
+impl<A> From<UnknownGroup> for GroupError<A> {
+    fn from(e: UnknownGroup) -> Self {
+        GroupError::Unknown(e)
+    }
+}
+
+impl<A: fmt::Debug> Display for GroupError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupError::Unknown(e) => e.fmt(f),
+            GroupError::Incomplete { group, addr } => write!(f, "value {addr:?} of commitment group {group} can not be computed"),
+        }
+    }
 }
 
 pub trait GraphBackend<S: Storage> {
     fn init(s: S, g: RTGraph<S>) -> Self;
     fn execute_until_input(&mut self) -> &S;
+    /// Executes the graph until the witness of the group is complete, then fills challenges derived after it
+    /// (see RTGraph::fill_challenges), unblocking advices which read them.
+    fn complete_group(&mut self, group: usize, transcript: &mut impl Transcript<S>) -> Result<(), GroupError<S::RawAddr>>;
+}
+
+/// Receives the constraint system of a circuit (as opposed to GraphBackend, which computes the witness).
+pub trait ConstraintBackend<C: Circuit> {
+    fn constraint(&mut self, constraint: &Constraint<C>);
+    fn table(&mut self, id: TableId, table: &Table<C::F>);
+    fn lookup(&mut self, lookup: &Lookup<C>);
+}
+
+/// Passes all constraints, tables and lookups of the circuit to the backend. Tables are passed before lookups.
+pub fn export_constraints<C: ConstraintLog + Lookups>(c: &C, backend: &mut impl ConstraintBackend<C>) {
+    for constraint in c.constraints().iter() {
+        backend.constraint(constraint);
+    }
+    for (id, table) in c.tables().iter().enumerate() {
+        backend.table(TableId(id), table);
+    }
+    for lookup in c.lookups().iter() {
+        backend.lookup(lookup);
+    }
 }
 
 pub trait TypedStorageAddr<S: Storage> {
@@ -133,4 +359,135 @@ where
     fn call(&self, storage: &mut S) {
         storage.write(&self.output, (*self.func)(storage.read(&self.input)));
     }
-}
\ No newline at end of file
+}
+
+/// Advice over field elements, compiled from circuit::FieldAdvice. Structs of the circuit are kept to assemble
+/// values of inputs and disassemble values of outputs (see SVStruct::from_values and SVStruct::to_values).
+pub struct FieldRuntimeAdvice<I, O, C, S>
+where
+    C: Circuit,
+    I: SVStruct<C>,
+    O: SVStruct<C>,
+    S: Storage,
+{
+    pub inputs: Vec<S::RawAddr>,
+    pub outputs: Vec<S::RawAddr>,
+    pub input: I,
+    pub output: O,
+    pub func: Arc<dyn Fn(I::FStruct) -> O::FStruct>,
+    pub _pd: PhantomData<C>,
+}
+
+impl<I, O, C, S> RTAdvice<S> for FieldRuntimeAdvice<I, O, C, S>
+where
+    C: Circuit,
+    I: SVStruct<C>,
+    O: SVStruct<C>,
+    S: Storage + ReaderOf<C::F> + WriterOf<C::F>,
+{
+    fn inputs(&self) -> Vec<S::RawAddr> {
+        self.inputs.clone()
+    }
+
+    fn outputs(&self) -> Vec<S::RawAddr> {
+        self.outputs.clone()
+    }
+
+    fn call(&self, storage: &mut S) {
+        self.call_local(storage, &mut LocalValues::new(&HashSet::new()))
+    }
+
+    fn supports_local(&self) -> bool {
+        true
+    }
+
+    fn call_local(&self, storage: &mut S, local: &mut LocalValues<S>) {
+        let mut values = self.inputs.iter().map(|addr| *local.get(storage, addr));
+        let values = self.output.to_values((*self.func)(self.input.from_values(&mut values)));
+        for (addr, value) in self.outputs.iter().zip_eq(values) {
+            local.put(storage, addr, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, Inputs, ToRawAddr},
+        gadgets::traits::bigint_arith::range_table,
+        test_utils::{square, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    /// Transcript which counts absorbed values, and squeezes their amount.
+    #[derive(Default)]
+    struct CountingTranscript {
+        absorbed: u64,
+    }
+
+    impl Transcript<TestStorage> for CountingTranscript {
+        fn absorb_group(&mut self, _storage: &TestStorage, _group: usize, witness: &[usize]) {
+            self.absorbed += witness.len() as u64;
+        }
+
+        fn squeeze_challenge(&mut self, storage: &mut TestStorage, addr: &usize) {
+            storage.put(addr, Fr::from(self.absorbed));
+        }
+    }
+
+    #[test]
+    fn test_fill_challenges() {
+        let mut s = TestStorage { data: vec![None; 4] };
+        let graph: RTGraph<TestStorage> = RTGraph::new(vec![0, 1], vec![], vec![], vec![], vec![vec![0, 1], vec![2]], vec![(0, 3)]);
+        let mut transcript = CountingTranscript::default();
+
+        assert_eq!(graph.fill_challenges(&mut s, 0, &mut transcript), Ok(()));
+        assert_eq!(s.get(&3), &Fr::from(2));
+        assert_eq!(graph.fill_challenges(&mut s, 2, &mut transcript), Err(UnknownGroup(2)));
+        assert_eq!(UnknownGroup(2).to_string(), "graph has no commitment group 2");
+        assert_eq!(transcript.absorbed, 2);
+    }
+
+    /// Backend recording what it receives, in order.
+    #[derive(Default)]
+    struct RecordingBackend {
+        received: Vec<String>,
+    }
+
+    impl ConstraintBackend<TestCircuit> for RecordingBackend {
+        fn constraint(&mut self, constraint: &Constraint<TestCircuit>) {
+            let degree = constraint.degree();
+            self.received.push(format!("constraint of {} terms, degree {degree}", constraint.terms.len()));
+        }
+
+        fn table(&mut self, id: TableId, table: &Table<Fr>) {
+            self.received.push(format!("table {} {} of {} rows", id.0, table.name, table.rows.len()));
+        }
+
+        fn lookup(&mut self, lookup: &Lookup<TestCircuit>) {
+            self.received.push(format!("lookup into {} of {:?}", lookup.table.0, lookup.sigs));
+        }
+    }
+
+    #[test]
+    fn test_export_constraints() {
+        let mut c = TestCircuit::new(BuildMode::ConstraintsOnly);
+        let x = c.alloc_input::<Fr>();
+        let table = range_table(&mut c, 4);
+        c.lookup(table, &[x]);
+        let y = square(&mut c, x);
+        c.lookup(table, &[y]);
+
+        let mut backend = RecordingBackend::default();
+        export_constraints(&c, &mut backend);
+        assert_eq!(backend.received, vec![
+            "constraint of 2 terms, degree 2".to_string(),
+            "table 0 range_4 of 4 rows".to_string(),
+            format!("lookup into 0 of {:?}", [x.to_raw_addr()]),
+            format!("lookup into 0 of {:?}", [y.to_raw_addr()]),
+        ]);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod storage;
-pub mod api;
\ No newline at end of file
+pub mod api;
+pub mod registry;
+pub mod sequential;
\ No newline at end of file
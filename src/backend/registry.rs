@@ -0,0 +1,311 @@
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display}, sync::Arc};
+
+use ff::PrimeField;
+use num_bigint::BigUint;
+
+use crate::{
+    circuit::{AdviceLog, Challenges, Circuit, FuncId, HasSigtype, Sig, Signals, TAdvice, ToRawAddr},
+    gadgets::traits::bigint_arith::split_limbs,
+};
+
+use super::{api::{KindCall, LocalValues, RTAdvice}, storage::{ReaderOf, Storage, WriterOf}};
+
+/// Function over field elements which can be described by its name and parameters, so that advices using it
+/// can be serialised (see RTAdvice::kinds) and restored by AdviceRegistry.
+pub trait AdviceKind<F>: 'static {
+    fn name(&self) -> &'static str;
+    /// Parameters, without whitespace, commas and parentheses.
+    fn params(&self) -> Vec<String>;
+    fn call(&self, inputs: &[F]) -> Vec<F>;
+}
+
+/// Advice kind which can be restored from its parameters.
+pub trait RestoreKind<F>: AdviceKind<F> + Sized {
+    const NAME: &'static str;
+
+    /// Returns the reason if parameters are malformed.
+    fn from_params(params: &[String]) -> Result<Self, String>;
+}
+
+fn field_to_string<F: PrimeField>(x: &F) -> String {
+    BigUint::from_bytes_le(x.to_repr().as_ref()).to_string()
+}
+
+fn field_from_str<F: PrimeField>(s: &str) -> Result<F, String> {
+    F::from_str_vartime(s).ok_or_else(|| format!("malformed field element {s}"))
+}
+
+/// Linear combination of inputs with coefficients.
+pub struct Lc<F> {
+    pub coeffs: Vec<F>,
+}
+
+impl<F: PrimeField> AdviceKind<F> for Lc<F> {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn params(&self) -> Vec<String> {
+        self.coeffs.iter().map(field_to_string).collect()
+    }
+
+    fn call(&self, inputs: &[F]) -> Vec<F> {
+        assert!(inputs.len() == self.coeffs.len(), "linear combination of {} values", self.coeffs.len());
+        vec![inputs.iter().zip(self.coeffs.iter()).map(|(x, coeff)| *x * coeff).sum()]
+    }
+}
+
+impl<F: PrimeField> RestoreKind<F> for Lc<F> {
+    const NAME: &'static str = "lc";
+
+    fn from_params(params: &[String]) -> Result<Self, String> {
+        Ok(Lc { coeffs: params.iter().map(|param| field_from_str(param)).collect::<Result<_, _>>()? })
+    }
+}
+
+/// Fifth power of the input.
+pub struct Pow5;
+
+impl<F: PrimeField> AdviceKind<F> for Pow5 {
+    fn name(&self) -> &'static str {
+        <Self as RestoreKind<F>>::NAME
+    }
+
+    fn params(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn call(&self, inputs: &[F]) -> Vec<F> {
+        let [x] = inputs else { panic!("pow5 of {} values", inputs.len()) };
+        vec![x.square().square() * x]
+    }
+}
+
+impl<F: PrimeField> RestoreKind<F> for Pow5 {
+    const NAME: &'static str = "pow5";
+
+    fn from_params(params: &[String]) -> Result<Self, String> {
+        params.is_empty().then_some(Pow5).ok_or_else(|| "pow5 has no parameters".to_string())
+    }
+}
+
+/// num_limbs limbs of the input in given base, least significant first, as advise_limbs computes them.
+pub struct SplitLimbs {
+    pub base: BigUint,
+    pub num_limbs: u32,
+}
+
+impl<F: PrimeField> AdviceKind<F> for SplitLimbs {
+    fn name(&self) -> &'static str {
+        <Self as RestoreKind<F>>::NAME
+    }
+
+    fn params(&self) -> Vec<String> {
+        vec![self.base.to_string(), self.num_limbs.to_string()]
+    }
+
+    fn call(&self, inputs: &[F]) -> Vec<F> {
+        let [x] = inputs else { panic!("split_limbs of {} values", inputs.len()) };
+        split_limbs(x, &self.base, self.num_limbs)
+    }
+}
+
+impl<F: PrimeField> RestoreKind<F> for SplitLimbs {
+    const NAME: &'static str = "split_limbs";
+
+    fn from_params(params: &[String]) -> Result<Self, String> {
+        let [base, num_limbs] = params else { return Err("split_limbs has parameters base and num_limbs".to_string()) };
+        Ok(SplitLimbs {
+            base: base.parse().map_err(|_| format!("malformed base {base}"))?,
+            num_limbs: num_limbs.parse().map_err(|_| format!("malformed amount of limbs {num_limbs}"))?,
+        })
+    }
+}
+
+/// Inverse of the input, zero for zero.
+pub struct Inverse;
+
+impl<F: PrimeField> AdviceKind<F> for Inverse {
+    fn name(&self) -> &'static str {
+        <Self as RestoreKind<F>>::NAME
+    }
+
+    fn params(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn call(&self, inputs: &[F]) -> Vec<F> {
+        let [x] = inputs else { panic!("inverse of {} values", inputs.len()) };
+        vec![x.invert().unwrap_or(F::ZERO)]
+    }
+}
+
+impl<F: PrimeField> RestoreKind<F> for Inverse {
+    const NAME: &'static str = "inverse";
+
+    fn from_params(params: &[String]) -> Result<Self, String> {
+        params.is_empty().then_some(Inverse).ok_or_else(|| "inverse has no parameters".to_string())
+    }
+}
+
+type KindParser<F> = fn(&[String]) -> Result<Arc<dyn AdviceKind<F>>, String>;
+
+fn restore<F, K: RestoreKind<F>>(params: &[String]) -> Result<Arc<dyn AdviceKind<F>>, String> {
+    Ok(Arc::new(K::from_params(params)?))
+}
+
+/// Why an advice kind can not be restored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// No kind is registered under the name.
+    Unregistered(String),
+    /// The kind rejects its parameters, for the reason given.
+    Params { name: String, reason: String },
+}
+
+impl Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreError::Unregistered(name) => write!(f, "advice kind {name} is not registered"),
+            RestoreError::Params { name, reason } => write!(f, "malformed parameters of advice kind {name}: {reason}"),
+        }
+    }
+}
+
+/// Advice kinds by name, used to restore serialised advices.
+pub struct AdviceRegistry<F> {
+    kinds: HashMap<&'static str, KindParser<F>>,
+}
+
+impl<F: PrimeField> AdviceRegistry<F> {
+    /// Creates a registry of builtin kinds: lc, pow5, split_limbs and inverse.
+    pub fn new() -> Self {
+        let mut registry = AdviceRegistry { kinds: HashMap::new() };
+        registry.register::<Lc<F>>()
+            .register::<Pow5>()
+            .register::<SplitLimbs>()
+            .register::<Inverse>();
+        registry
+    }
+
+    /// Registers the kind. Panics if a kind with the same name is already registered.
+    pub fn register<K: RestoreKind<F>>(&mut self) -> &mut Self {
+        assert!(!self.kinds.contains_key(K::NAME), "advice kind {} is already registered", K::NAME);
+        self.kinds.insert(K::NAME, restore::<F, K>);
+        self
+    }
+
+    /// Restores the kind from its name and parameters.
+    pub fn restore(&self, name: &str, params: &[String]) -> Result<Arc<dyn AdviceKind<F>>, RestoreError> {
+        let parse = self.kinds.get(name).ok_or_else(|| RestoreError::Unregistered(name.to_string()))?;
+        parse(params).map_err(|reason| RestoreError::Params { name: name.to_string(), reason })
+    }
+}
+
+impl<F: PrimeField> Default for AdviceRegistry<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Advice of the execution graph calling an advice kind.
+pub struct KindAdvice<S: Storage, F> {
+    pub kind: Arc<dyn AdviceKind<F>>,
+    pub inputs: Vec<S::RawAddr>,
+    pub outputs: Vec<S::RawAddr>,
+}
+
+impl<S, F> RTAdvice<S> for KindAdvice<S, F>
+where
+    S: Storage + ReaderOf<F> + WriterOf<F>,
+    F: Clone + 'static,
+{
+    fn inputs(&self) -> Vec<S::RawAddr> {
+        self.inputs.clone()
+    }
+
+    fn outputs(&self) -> Vec<S::RawAddr> {
+        self.outputs.clone()
+    }
+
+    fn call(&self, storage: &mut S) {
+        self.call_local(storage, &mut LocalValues::new(&HashSet::new()))
+    }
+
+    fn supports_local(&self) -> bool {
+        true
+    }
+
+    fn call_local(&self, storage: &mut S, local: &mut LocalValues<S>) {
+        let inputs: Vec<F> = self.inputs.iter().map(|addr| local.get(storage, addr).clone()).collect();
+        let outputs = self.kind.call(&inputs);
+        assert!(outputs.len() == self.outputs.len(), "advice {} returned {} values instead of {}", self.kind.name(), outputs.len(), self.outputs.len());
+        for (addr, value) in self.outputs.iter().zip(outputs) {
+            local.put(storage, addr, value);
+        }
+    }
+
+    fn kinds(&self) -> Option<Vec<KindCall<S::RawAddr>>> {
+        Some(vec![KindCall {
+            name: self.kind.name(),
+            params: self.kind.params(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+        }])
+    }
+}
+
+/// Recorded advice calling an advice kind, compiled into KindAdvice.
+pub struct KindRecord<C: Circuit> {
+    kind: Arc<dyn AdviceKind<C::F>>,
+    inputs: Vec<C::RawAddr>,
+    outputs: Vec<C::RawAddr>,
+}
+
+impl<C, S> TAdvice<C, S> for KindRecord<C>
+where
+    C: Circuit,
+    S: Storage + ReaderOf<C::F> + WriterOf<C::F> + 'static,
+{
+    fn compile(&self, _s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> Box<dyn RTAdvice<S>> {
+        Box::new(KindAdvice::<S, C::F> {
+            kind: self.kind.clone(),
+            inputs: self.inputs.iter().map(|addr| mapping(*addr)).collect(),
+            outputs: self.outputs.iter().map(|addr| mapping(*addr)).collect(),
+        })
+    }
+}
+
+/// Advices by registered kinds, as opposed to anonymous closures (see Advices). Such advices can be saved together
+/// with the compiled execution graph.
+pub trait KindAdvices: AdviceLog + Signals + Challenges {
+    /// Advises primary signals.
+    #[track_caller]
+    fn advise_kind<K: AdviceKind<Self::F>>(&mut self, kind: K, inputs: &[Sig<Self, Self::F>], num_outputs: usize) -> Vec<Sig<Self, Self::F>>
+    where
+        Self: 'static,
+        Self::Config: HasSigtype<Self::F>,
+        Self::Storage: ReaderOf<Self::F> + WriterOf<Self::F> + 'static,
+    {
+        let outputs: Vec<Sig<Self, Self::F>> = (0..num_outputs).map(|_| self.alloc_sig()).collect();
+        self.advise_kind_into(kind, inputs, &outputs);
+        outputs
+    }
+
+    /// Advises already allocated signals.
+    #[track_caller]
+    fn advise_kind_into<K: AdviceKind<Self::F>>(&mut self, kind: K, inputs: &[Sig<Self, Self::F>], outputs: &[Sig<Self, Self::F>])
+    where
+        Self: 'static,
+        Self::Config: HasSigtype<Self::F>,
+        Self::Storage: ReaderOf<Self::F> + WriterOf<Self::F> + 'static,
+    {
+        let inputs: Vec<Self::RawAddr> = inputs.iter().map(|sig| sig.to_raw_addr()).collect();
+        let outputs: Vec<Self::RawAddr> = outputs.iter().map(|sig| sig.to_raw_addr()).collect();
+        let func_id = FuncId::of::<K>(kind.params());
+        let record = KindRecord::<Self> { kind: Arc::new(kind), inputs: inputs.clone(), outputs: outputs.clone() };
+        self._log_record(inputs, outputs, Some(func_id), move || Box::new(record));
+    }
+}
+
+impl<C: AdviceLog + Signals + Challenges> KindAdvices for C {}
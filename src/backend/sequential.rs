@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+
+use super::{
+    api::{GraphBackend, GroupError, RTGraph, Transcript, UnknownGroup},
+    storage::Storage,
+};
+
+/// Backend executing advices of the graph one by one, in their order. Inputs and constants of the graph have to be in
+/// storage before init, challenges are written by complete_group.
+pub struct SequentialBackend<S: Storage> {
+    storage: S,
+    graph: RTGraph<S>,
+    /// Advices not executed yet, in order.
+    pending: Vec<usize>,
+    /// Addresses whose values are in storage.
+    computed: HashSet<S::RawAddr>,
+}
+
+impl<S: Storage> SequentialBackend<S> {
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns the storage, e.g. to read the witness once all groups are complete.
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: Storage> GraphBackend<S> for SequentialBackend<S> {
+    fn init(s: S, g: RTGraph<S>) -> Self {
+        let computed = g.inputs().iter().chain(g.constants().iter()).copied().collect();
+        let pending = (0..g.advices().len()).collect();
+        SequentialBackend { storage: s, graph: g, pending, computed }
+    }
+
+    /// Executes every advice whose inputs are computed. Stops when the remaining ones wait for challenges.
+    fn execute_until_input(&mut self) -> &S {
+        let advices = self.graph.advices();
+        self.pending.retain(|i| {
+            let advice = &advices[*i].advice;
+            if !advice.inputs().iter().all(|addr| self.computed.contains(addr)) {
+                return true;
+            }
+            advice.call(&mut self.storage);
+            self.computed.extend(advice.outputs());
+            false
+        });
+        &self.storage
+    }
+
+    fn complete_group(&mut self, group: usize, transcript: &mut impl Transcript<S>) -> Result<(), GroupError<S::RawAddr>> {
+        self.execute_until_input();
+        let witness = self.graph.groups().get(group).ok_or(UnknownGroup(group))?;
+        if let Some(addr) = witness.iter().find(|addr| !self.computed.contains(addr)) {
+            return Err(GroupError::Incomplete { group, addr: *addr });
+        }
+        self.graph.fill_challenges(&mut self.storage, group, transcript)?;
+        self.computed.extend(self.graph.challenges().iter().filter(|(after, _)| *after == group).map(|(_, addr)| *addr));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Challenges, Inputs, Signals, ToRawAddr},
+        middleend::compiler::compile,
+        test_utils::{square, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    /// Transcript squeezing the sum of all absorbed values.
+    #[derive(Default)]
+    struct SumTranscript {
+        sum: Fr,
+    }
+
+    impl Transcript<TestStorage> for SumTranscript {
+        fn absorb_group(&mut self, storage: &TestStorage, _group: usize, witness: &[usize]) {
+            self.sum += witness.iter().map(|addr| *storage.get(addr)).sum::<Fr>();
+        }
+
+        fn squeeze_challenge(&mut self, storage: &mut TestStorage, addr: &usize) {
+            storage.put(addr, self.sum);
+        }
+    }
+
+    #[test]
+    fn test_complete_group() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let x = c.alloc_input::<Fr>();
+        let x2 = square(&mut c, x);
+        let ch = c.alloc_challenge::<Fr>(0);
+        c.group = 1;
+        let ch: TSig = c.sig_from_raw_addr(ch.to_raw_addr());
+        let y = c.advise_sigs(&[x, ch], 1, |v| vec![v[0] * v[1]])[0];
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        s.put(&mapping[&x.to_raw_addr()], Fr::from(3));
+        let mut backend = SequentialBackend::init(s, graph);
+        let mut transcript = SumTranscript::default();
+
+        // y waits for the challenge, so group 1 is not complete before group 0.
+        assert_eq!(backend.execute_until_input().get(&mapping[&x2.to_raw_addr()]), &Fr::from(9));
+        assert_eq!(
+            backend.complete_group(1, &mut transcript),
+            Err(GroupError::Incomplete { group: 1, addr: mapping[&y.to_raw_addr()] }),
+        );
+        assert_eq!(backend.complete_group(0, &mut transcript), Ok(()));
+        assert_eq!(backend.complete_group(1, &mut transcript), Ok(()));
+        assert_eq!(backend.complete_group(2, &mut transcript), Err(GroupError::Unknown(UnknownGroup(2))));
+
+        // The challenge is 3 + 9 absorbed from group 0.
+        let s = backend.into_storage();
+        assert_eq!(s.get(&mapping[&y.to_raw_addr()]), &Fr::from(3 * 12));
+    }
+}
@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{any::TypeId, fmt::Debug, hash::Hash, marker::PhantomData};
 
 pub struct TypedAddr<S: Storage, T> {
     pub addr: S::RawAddr,
@@ -6,7 +6,7 @@ pub struct TypedAddr<S: Storage, T> {
 }
 
 pub trait Storage {
-    type RawAddr;
+    type RawAddr: Copy + Eq + Hash + Debug;
 
     fn to_raw<T>(ta: &TypedAddr<Self, T>) -> Self::RawAddr where Self: Sized;
 }
@@ -15,6 +15,16 @@ pub trait AllocatorOf<T>: Storage {
     fn allocate(&mut self) -> <Self as Storage>::RawAddr;
 }
 
+/// Allocation of values whose type is only known at runtime.
+pub trait RawAllocator: Storage {
+    fn allocate_raw(&mut self, ty: TypeId) -> <Self as Storage>::RawAddr;
+}
+
+/// Types of allocated values, as given to RawAllocator::allocate_raw or AllocatorOf::allocate.
+pub trait TypedStorage: Storage {
+    fn type_of(&self, addr: &Self::RawAddr) -> TypeId;
+}
+
 pub trait WriterOf<T>: Storage {
     fn put(&mut self, addr: &Self::RawAddr, val: T);
 }
@@ -1,9 +1,9 @@
-use std::{any::TypeId, marker::PhantomData, sync::Arc, vec};
+use std::{any::TypeId, fmt::Debug, hash::Hash, marker::PhantomData, panic::Location, sync::Arc, vec};
 
-use ff::PrimeField;
+use ff::{Field, PrimeField};
 use num_bigint::BigUint;
 
-use crate::backend::{api::{AllowsStruct, RTAdvice, RuntimeAdvice}, storage::{Storage, TypedAddr}};
+use crate::{backend::{api::{AllowsStruct, FieldRuntimeAdvice, RTAdvice, RuntimeAdvice}, storage::{ReaderOf, Storage, TypedAddr, WriterOf}}, gadgets::cost::CostEstimate};
 
 
 
@@ -82,9 +82,15 @@ pub trait HasSigtype<T: 'static> : Circuit + HasVartype<T> + Conversion<T, Self:
 // Circuit flags
 pub trait Circuit : Conversion<Self::F, Self::F> + Sized{
     type F : PrimeField;
-    type RawAddr : Copy;
+    type RawAddr : Copy + Ord + Hash + Debug;
+    /// Both a type-level marker of allowed var/sig types (see HasVartype, HasSigtype) and a carrier
+    /// of runtime parameters queried by gadgets (see e.g. PoseidonConfig, RangecheckConfig).
     type Config;
 
+    /// Returns the configuration this circuit is being built with.
+    fn config(&self) -> &Self::Config;
+    /// Returns all allocated raw addresses, in order of allocation.
+    fn raw_addrs(&self) -> Vec<Self::RawAddr>;
     fn inner_type(&self, addr: Self::RawAddr) -> TypeId;
     /// Constructs a new raw address with inner type T. All boolean flags are unset, all other flags are None.
     fn _alloc_raw<T: 'static>(&mut self) -> Self::RawAddr where Self::Config : HasVartype<T>;
@@ -130,6 +136,182 @@ pub trait ConstantFlag : Circuit + VariableFlag {
     fn _set_const_flag(&mut self, addr: Self::RawAddr, value: bool);
 }
 
+pub trait PublicFlag : Circuit + SignalFlag {
+    /// Checks whether value is a public output of the circuit.
+    fn is_public(&self, addr: Self::RawAddr) -> bool;
+    /// Unsafe. Sets public flag.
+    fn _set_public_flag(&mut self, addr: Self::RawAddr, value: bool);
+}
+
+pub trait ChallengeFlag : Circuit + VariableFlag {
+    /// Returns the commitment group whose sealing the challenge is derived after. None if value is not a challenge.
+    fn challenge_after(&self, addr: Self::RawAddr) -> Option<usize>;
+    /// Unsafe. Makes value a challenge derived after the group, or unsets the challenge flag.
+    fn _set_challenge_after(&mut self, addr: Self::RawAddr, value: Option<usize>);
+}
+
+pub trait ChallengeDependencyFlag : Circuit + VariableFlag {
+    /// Returns the latest group among those whose challenges the value is computed from, directly or through other
+    /// advices. None if the value does not depend on challenges.
+    fn challenge_dependency(&self, addr: Self::RawAddr) -> Option<usize>;
+    /// Unsafe. Sets the latest group the value depends on challenges of.
+    fn _set_challenge_dependency(&mut self, addr: Self::RawAddr, value: Option<usize>);
+}
+
+pub trait InputFlag : Circuit + VariableFlag {
+    /// Checks whether value is an external input, i.e. provided to the execution graph rather than computed by it.
+    fn is_input(&self, addr: Self::RawAddr) -> bool;
+    /// Unsafe. Sets input flag.
+    fn _set_input_flag(&mut self, addr: Self::RawAddr, value: bool);
+}
+
+pub trait CommitmentGroups : Circuit {
+    /// Returns the group newly committed signals are placed in.
+    fn current_group(&self) -> usize;
+    /// Checks whether a group is sealed, i.e. already committed and closed for new signals.
+    fn is_sealed(&self, group: usize) -> bool;
+    /// Returns commitment group of a value. None if value is not committed.
+    fn group(&self, addr: Self::RawAddr) -> Option<usize>;
+    /// Unsafe. Sets commitment group of a value.
+    fn _set_group(&mut self, addr: Self::RawAddr, value: Option<usize>);
+}
+
+pub trait AliasFlag : Circuit + VariableFlag {
+    /// Returns the value this one was merged into and which is used in its place. None if value is not merged.
+    fn alias(&self, addr: Self::RawAddr) -> Option<Self::RawAddr>;
+    /// Unsafe. Sets alias of a value.
+    fn _set_alias(&mut self, addr: Self::RawAddr, value: Option<Self::RawAddr>);
+
+    /// Follows aliases to the value which is actually used.
+    fn resolve(&self, mut addr: Self::RawAddr) -> Self::RawAddr {
+        while let Some(alias) = self.alias(addr) {
+            addr = alias;
+        }
+        addr
+    }
+}
+
+// --------------------------------------------
+// Checkpoints
+
+/// State of a circuit apart from its logs (see AdviceLog, ConstraintLog, Lookups): allocated addresses with all their
+/// flags, the current commitment group and so on. Circuits implementing it get Checkpoints.
+pub trait Snapshots : Circuit {
+    type Snapshot;
+
+    fn snapshot(&self) -> Self::Snapshot;
+    /// Replaces the state by the snapshot, including the set of allocated addresses.
+    fn restore(&mut self, snapshot: Self::Snapshot);
+}
+
+/// Snapshot of the circuit together with lengths of the logs, which only grow.
+pub struct Checkpoint<C: Snapshots> {
+    snapshot: C::Snapshot,
+    addrs: usize,
+    advices: usize,
+    constraints: usize,
+    tables: usize,
+    lookups: usize,
+}
+
+/// Everything undone by a rollback, see Checkpoints::replay.
+pub struct Changes<C: Snapshots + AdviceLog + ConstraintLog + Lookups> {
+    snapshot: C::Snapshot,
+    advices: Vec<AdviceRecord<C, C::Storage>>,
+    constraints: Vec<Constraint<C>>,
+    tables: Vec<Table<C::F>>,
+    lookups: Vec<Lookup<C>>,
+}
+
+pub trait Checkpoints : Snapshots + AdviceLog + ConstraintLog + Lookups {
+    /// Marks the current state of the circuit.
+    fn checkpoint(&self) -> Checkpoint<Self> {
+        Checkpoint {
+            snapshot: self.snapshot(),
+            addrs: self.raw_addrs().len(),
+            advices: self.advice_log().len(),
+            constraints: self.constraints().len(),
+            tables: self.tables().len(),
+            lookups: self.lookups().len(),
+        }
+    }
+
+    /// Undoes everything done after the checkpoint was taken: allocations, flags and bounds (including the ones
+    /// changed on older addresses), advices, constraints, tables and lookups. Checkpoints taken after cp become invalid.
+    fn rollback(&mut self, cp: Checkpoint<Self>) -> Changes<Self> {
+        let changes = Changes {
+            snapshot: self.snapshot(),
+            advices: self.advice_log_mut().split_off(cp.advices),
+            constraints: self.constraints_mut().split_off(cp.constraints),
+            tables: self.tables_mut().split_off(cp.tables),
+            lookups: self.lookups_mut().split_off(cp.lookups),
+        };
+        self.restore(cp.snapshot);
+        changes
+    }
+
+    /// Redoes changes undone by a rollback. The circuit must be in the state of the checkpoint they were rolled back
+    /// to.
+    fn replay(&mut self, changes: Changes<Self>) {
+        self.restore(changes.snapshot);
+        self.advice_log_mut().extend(changes.advices);
+        self.constraints_mut().extend(changes.constraints);
+        self.tables_mut().extend(changes.tables);
+        self.lookups_mut().extend(changes.lookups);
+    }
+
+    /// Returns the resources allocated after the checkpoint was taken.
+    fn cost_since(&self, cp: &Checkpoint<Self>) -> CostEstimate {
+        CostEstimate {
+            signals: self.raw_addrs()[cp.addrs..].iter().filter(|addr| self.is_sig(**addr)).count(),
+            constraints: self.constraints().len() - cp.constraints,
+            advices: self.advice_log().len() - cp.advices,
+            lookups: self.lookups().len() - cp.lookups,
+        }
+    }
+
+    /// Builds both alternatives tentatively and keeps the one with the smaller CostEstimate::weight (b on ties).
+    /// Each alternative is built once, the losing one is rolled back.
+    fn cheaper_of<R>(&mut self, a: impl FnOnce(&mut Self) -> R, b: impl FnOnce(&mut Self) -> R) -> R {
+        let cp = self.checkpoint();
+        let ret_a = a(self);
+        let cost_a = self.cost_since(&cp);
+        let changes_a = self.rollback(cp);
+
+        let cp = self.checkpoint();
+        let ret_b = b(self);
+        let cost_b = self.cost_since(&cp);
+        if cost_b.weight() <= cost_a.weight() {
+            ret_b
+        } else {
+            self.rollback(cp);
+            self.replay(changes_a);
+            ret_a
+        }
+    }
+}
+
+impl<C: Snapshots + AdviceLog + ConstraintLog + Lookups> Checkpoints for C {}
+
+// --------------------------------------------
+// Namespaces
+
+pub trait Namespaces : Circuit {
+    /// Returns the current namespace, with nested names separated by '/'. Empty at top level.
+    fn namespace(&self) -> String;
+    fn push_namespace(&mut self, name: &str);
+    /// Panics at top level.
+    fn pop_namespace(&mut self);
+
+    /// Runs f inside of a nested namespace.
+    fn in_namespace<R>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.push_namespace(name);
+        let ret = f(self);
+        self.pop_namespace();
+        ret
+    }
+}
+
 // --------------------------------------------
 // Typed wrappers
 
@@ -296,6 +478,56 @@ where
     }
 }
 
+pub trait Commitments : Signals + ConstantFlag + CommitmentGroups + ChallengeDependencyFlag {
+    /// Promotes a variable to a primary signal in place, placing it in the current commitment group.
+    /// Panics on constants, on values which already belong to a sealed group, if the current group is sealed, and if
+    /// the value is computed from challenges derived after the current group.
+    fn commit<T: 'static>(&mut self, var: Var<Self, T>) -> Sig<Self, T> where Self::Config : HasSigtype<T>;
+}
+
+impl<C : Signals + ConstantFlag + CommitmentGroups + ChallengeDependencyFlag> Commitments for C {
+    fn commit<T: 'static>(&mut self, var: Var<Self, T>) -> Sig<Self, T> where Self::Config : HasSigtype<T> {
+        let raw_addr = var.raw_addr;
+        assert!(!self.is_const(raw_addr), "constants can not be committed");
+        if let Some(group) = self.group(raw_addr) {
+            assert!(!self.is_sealed(group), "value is already sealed in commitment group {group}");
+        }
+        let group = self.current_group();
+        assert!(!self.is_sealed(group), "current commitment group {group} is sealed");
+        if let Some(latest) = self.challenge_dependency(raw_addr) {
+            assert!(group > latest, "value computed from a challenge derived after group {latest} is committed to group {group}");
+        }
+        self._set_sig_flag(raw_addr, true);
+        self._set_primary_flag(raw_addr, true);
+        self._set_group(raw_addr, Some(group));
+        self.sig_from_raw_addr(raw_addr)
+    }
+}
+
+pub trait Inputs : Signals + InputFlag {
+    /// Allocates signal provided externally to the execution graph (e.g. public input or private witness), and commits it.
+    fn alloc_input<T: 'static>(&mut self) -> Sig<Self, T> where Self::Config : HasSigtype<T>;
+}
+
+impl<C : Signals + InputFlag> Inputs for C {
+    fn alloc_input<T: 'static>(&mut self) -> Sig<Self, T> where Self::Config : HasSigtype<T> {
+        let sig = self.alloc_sig();
+        self._set_input_flag(sig.raw_addr, true);
+        sig
+    }
+}
+
+pub trait Publics : Signals + PublicFlag {
+    /// Declares signal to be a public output of the circuit.
+    fn make_public<T: 'static>(&mut self, sig: Sig<Self, T>) where Self::Config : HasSigtype<T>;
+}
+
+impl<C : Signals + PublicFlag> Publics for C {
+    fn make_public<T: 'static>(&mut self, sig: Sig<Self, T>) where Self::Config : HasSigtype<T> {
+        self._set_public_flag(sig.raw_addr, true);
+    }
+}
+
 // ---------CONSTS---------
 
 pub trait Constants : Circuit + ConstantFlag {
@@ -434,6 +666,103 @@ where
     }
 }
 
+// ---------CHALLENGES---------
+
+pub trait Challenges : Circuit + SignalFlag + ChallengeFlag + ChallengeDependencyFlag + CommitmentGroups {
+    fn challenge_from_raw_addr<T: 'static>(&self, raw_addr: Self::RawAddr) -> Challenge<Self, T> where Self::Config : HasSigtype<T>;
+    /// Allocates a challenge derived after the commitment group after_group is sealed.
+    /// Challenges are (uncommitted) signals, so they can be read by advices and used in constraints.
+    fn alloc_challenge<T: 'static>(&mut self, after_group: usize) -> Challenge<Self, T> where Self::Config : HasSigtype<T>;
+
+    /// Checks an advice before it is recorded: it must not write challenges, and must not write to a commitment group
+    /// values computed from challenges derived after that group is sealed, whether it reads such challenges directly
+    /// or values computed from them. Records which challenges the outputs depend on, for later checks. Panics if the
+    /// advice is invalid. Called for every recorded advice (see AdviceLog::_log_advice), in every build mode.
+    fn _check_advice(&mut self, inputs: &[Self::RawAddr], outputs: &[Self::RawAddr]) {
+        let latest = inputs.iter()
+            .filter_map(|addr| self.challenge_after(*addr).max(self.challenge_dependency(*addr)))
+            .max();
+        for addr in outputs {
+            assert!(self.challenge_after(*addr).is_none(), "challenges can not be written by advices");
+            if let (Some(latest), Some(group)) = (latest, self.group(*addr)) {
+                assert!(group > latest, "advice writes to commitment group {group} using a challenge derived after group {latest}");
+            }
+        }
+        if latest.is_some() {
+            for addr in outputs {
+                let dependency = self.challenge_dependency(*addr).max(latest);
+                self._set_challenge_dependency(*addr, dependency);
+            }
+        }
+    }
+}
+
+impl<C : Circuit + SignalFlag + ChallengeFlag + ChallengeDependencyFlag + CommitmentGroups> Challenges for C {
+    fn challenge_from_raw_addr<T: 'static>(&self, raw_addr: Self::RawAddr) -> Challenge<Self, T> where Self::Config : HasSigtype<T> {
+        assert!(self.inner_type(raw_addr) == TypeId::of::<T>());
+        assert!(self.is_var(raw_addr));
+        assert!(self.is_sig(raw_addr));
+        assert!(self.challenge_after(raw_addr).is_some());
+        Challenge {raw_addr, _marker : PhantomData}
+    }
+
+    fn alloc_challenge<T: 'static>(&mut self, after_group: usize) -> Challenge<Self, T> where Self::Config : HasSigtype<T> {
+        let raw_addr = self._alloc_raw::<T>();
+        self._set_var_flag(raw_addr, true);
+        self._set_sig_flag(raw_addr, true);
+        self._set_challenge_after(raw_addr, Some(after_group));
+        self.challenge_from_raw_addr(raw_addr)
+    }
+}
+
+pub struct Challenge<C, T: 'static>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    raw_addr : C::RawAddr,
+    _marker : PhantomData<T>,
+}
+
+impl<C, T: 'static> Clone for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    fn clone(&self) -> Self {
+        Self { raw_addr: self.raw_addr.clone(), _marker: PhantomData }
+    }
+}
+
+impl<C, T: 'static> Copy for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>
+{}
+
+impl<C, T1: 'static, T2: 'static>
+    _Into<Var<C, T2>> for Challenge<C, T1>
+where
+    C: Circuit + Conversion<T1, T2>,
+    C::Config: HasSigtype<T1> + HasSigtype<T2>,
+{
+    /// Converts the challenge with inner type T1 into variable with outer type T2.
+    #[inline(always)]
+    fn _into(self) -> Var<C, T2> {
+        Var { raw_addr : self.raw_addr, _marker : PhantomData }
+    }
+}
+
+impl<C, T: 'static> ToRawAddr<C> for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    fn to_raw_addr(&self) -> <C as Circuit>::RawAddr {
+        self.raw_addr
+    }
+}
+
 // --------- Access permissions -----------
 // --------- Untyped permission markers over raw_addr ------------
 
@@ -478,6 +807,16 @@ where
     }
 }
 
+impl<C, T: 'static> _Into<RWPermit<C>> for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    fn _into(self) -> RWPermit<C> {
+        RWPermit { raw_addr: self.raw_addr }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct CsPermit<C: Circuit> {
     raw_addr : C::RawAddr,
@@ -499,62 +838,206 @@ where
     }
 }
 
+impl<C, T: 'static> _Into<CsPermit<C>> for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    fn _into(self) -> CsPermit<C> {
+        CsPermit { raw_addr: self.raw_addr }
+    }
+}
+
 
 // --------- RWSTRUCT --------
 
 pub trait SVStruct<C: Circuit> {
     type FStruct;
-    
-    fn alloc_to(c: &mut C) -> Self;
+
+    /// Raw addresses of all values in this struct, in serialization order.
+    fn raw_addrs(&self) -> Vec<C::RawAddr>;
+    /// Assembles the value of this struct from the values at raw_addrs, in serialization order.
+    fn from_values(&self, values: &mut dyn Iterator<Item = C::F>) -> Self::FStruct;
+    /// Values at raw_addrs, in serialization order. Panics if the value does not fit the shape of this struct.
+    fn to_values(&self, value: Self::FStruct) -> Vec<C::F>;
 }
 
-pub trait CompileableStruct<C, S, F, T>: SVStruct<C>
+pub trait CompileableStruct<C, S, T>: SVStruct<C>
 where
     C: Circuit,
     S: Storage,
-    F: Fn(C::RawAddr) -> S::RawAddr,
     S: AllowsStruct<T>,
 {
-    fn compile(&self, s: &mut S, mapping: F) -> T;
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> T;
+}
+
+/// Struct whose shape is known statically, so it can be allocated as an advice output (see Advices::advise).
+pub trait StaticSVStruct<C: Circuit> : SVStruct<C> {
+    fn alloc_to(c: &mut C) -> Self;
+}
+
+/// Struct whose size is only known at build time, e.g. a vector. Shape describes its size.
+pub trait SizedSVStruct<C: Circuit> : SVStruct<C> {
+    type Shape;
+
+    fn alloc_to_with(c: &mut C, shape: &Self::Shape) -> Self;
 }
 
 
 impl<C: Circuit> SVStruct<C> for () {
     type FStruct = ();
 
-    fn alloc_to(c: &mut C) -> Self {}
+    fn raw_addrs(&self) -> Vec<C::RawAddr> {
+        vec![]
+    }
+
+    fn from_values(&self, _values: &mut dyn Iterator<Item = C::F>) {}
+
+    fn to_values(&self, _value: ()) -> Vec<C::F> {
+        vec![]
+    }
 }
 
 
-impl<C, S, F> CompileableStruct<C, S, F, ()> for ()
+impl<C, S> CompileableStruct<C, S, ()> for ()
 where
     C: Circuit,
     S: Storage,
-    F: Fn(C::RawAddr) -> S::RawAddr,
 {
-    fn compile(&self, s: &mut S, mapping: F) -> () {}
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> () {}
+}
+
+impl<C: Circuit> StaticSVStruct<C> for () {
+    fn alloc_to(_c: &mut C) -> Self {}
+}
+
+impl<C: Circuit> SizedSVStruct<C> for () {
+    type Shape = ();
+
+    fn alloc_to_with(_c: &mut C, _shape: &()) -> Self {}
 }
 
 impl<T, C> SVStruct<C> for Sig<C, T>
 where 
-    C: Circuit,
+    C: Circuit + Signals,
     C::Config: HasSigtype<T>,
 {
     type FStruct = C::F;
 
+    fn raw_addrs(&self) -> Vec<C::RawAddr> {
+        vec![self.raw_addr]
+    }
+
+    fn from_values(&self, values: &mut dyn Iterator<Item = C::F>) -> C::F {
+        values.next().expect("not enough values")
+    }
+
+    fn to_values(&self, value: C::F) -> Vec<C::F> {
+        vec![value]
+    }
+}
+
+impl<C, S, T> CompileableStruct<C, S, TypedAddr<S, T>> for Sig<C, T>
+where
+    C: Circuit + Signals,
+    C:: Config: HasSigtype<T>,
+    S: Storage + AllowsStruct<TypedAddr<S, T>>,
+{
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> TypedAddr<S, T> {
+        TypedAddr {
+            addr: mapping(self.raw_addr),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, C> StaticSVStruct<C> for Sig<C, T>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<T>,
+{
     fn alloc_to(c: &mut C) -> Self {
-        todo!()
+        c.alloc_sig()
+    }
+}
+
+impl<T, C> SizedSVStruct<C> for Sig<C, T>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<T>,
+{
+    type Shape = ();
+
+    fn alloc_to_with(c: &mut C, _shape: &()) -> Self {
+        Self::alloc_to(c)
+    }
+}
+
+impl<C: Circuit, ST: SVStruct<C>> SVStruct<C> for Vec<ST> {
+    type FStruct = Vec<ST::FStruct>;
+
+    fn raw_addrs(&self) -> Vec<C::RawAddr> {
+        self.iter().flat_map(|item| item.raw_addrs()).collect()
+    }
+
+    fn from_values(&self, values: &mut dyn Iterator<Item = C::F>) -> Self::FStruct {
+        self.iter().map(|item| item.from_values(values)).collect()
+    }
+
+    fn to_values(&self, value: Self::FStruct) -> Vec<C::F> {
+        assert!(value.len() == self.len(), "vector of length {} has {} values", self.len(), value.len());
+        self.iter().zip(value).flat_map(|(item, value)| item.to_values(value)).collect()
+    }
+}
+
+impl<C, S, ST, DT> CompileableStruct<C, S, Vec<DT>> for Vec<ST>
+where
+    C: Circuit,
+    S: Storage + AllowsStruct<DT>,
+    ST: CompileableStruct<C, S, DT>,
+{
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> Vec<DT> {
+        self.iter().map(|item| item.compile(s, mapping)).collect()
+    }
+}
+
+impl<C: Circuit, ST: SizedSVStruct<C>> SizedSVStruct<C> for Vec<ST> {
+    /// Length and shape of every element.
+    type Shape = (usize, ST::Shape);
+
+    fn alloc_to_with(c: &mut C, shape: &Self::Shape) -> Self {
+        (0..shape.0).map(|_| ST::alloc_to_with(c, &shape.1)).collect()
+    }
+}
+
+/// Challenges are filled by the backend, so they are not StaticSVStruct and can only be advice inputs.
+impl<T, C> SVStruct<C> for Challenge<C, T>
+where
+    C: Circuit,
+    C::Config: HasSigtype<T>,
+{
+    type FStruct = C::F;
+
+    fn raw_addrs(&self) -> Vec<C::RawAddr> {
+        vec![self.raw_addr]
+    }
+
+    fn from_values(&self, values: &mut dyn Iterator<Item = C::F>) -> C::F {
+        values.next().expect("not enough values")
+    }
+
+    fn to_values(&self, value: C::F) -> Vec<C::F> {
+        vec![value]
     }
 }
 
-impl<C, S, F, T> CompileableStruct<C, S, F, TypedAddr<S, T>> for Sig<C, T>
+impl<C, S, T> CompileableStruct<C, S, TypedAddr<S, T>> for Challenge<C, T>
 where
     C: Circuit,
     C:: Config: HasSigtype<T>,
     S: Storage + AllowsStruct<TypedAddr<S, T>>,
-    F: Fn(C::RawAddr) -> S::RawAddr,
 {
-    fn compile(&self, s: &mut S, mapping: F) -> TypedAddr<S, T> {
+    fn compile(&self, _s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> TypedAddr<S, T> {
         TypedAddr {
             addr: mapping(self.raw_addr),
             _pd: PhantomData,
@@ -569,65 +1052,559 @@ pub trait CsStruct<C: Circuit> :  SVStruct<C>{
 }
 
 
-// --------- ADVICES ---------
+// --------- CONSTRAINTS ---------
+
+/// Polynomial constraint sum_i coeff_i * prod_j sig_ij = 0. A term without signals is a constant term.
+pub struct Constraint<C: Circuit> {
+    pub terms: Vec<(C::F, Vec<C::RawAddr>)>,
+    /// Namespace the constraint was recorded in, set by ConstraintLog::constrain.
+    pub namespace: String,
+}
+
+impl<C: Circuit> Clone for Constraint<C> {
+    fn clone(&self) -> Self {
+        Self { terms: self.terms.clone(), namespace: self.namespace.clone() }
+    }
+}
+
+impl<C: Circuit> Constraint<C> {
+    pub fn new() -> Self {
+        Constraint { terms: vec![], namespace: String::new() }
+    }
 
+    /// Adds the term coeff * prod(sigs).
+    pub fn term(mut self, coeff: C::F, sigs: &[CsPermit<C>]) -> Self {
+        self.terms.push((coeff, sigs.iter().map(|sig| sig.to_raw_addr()).collect()));
+        self
+    }
+
+    pub fn degree(&self) -> usize {
+        self.terms.iter().map(|(_, sigs)| sigs.len()).max().unwrap_or(0)
+    }
+
+    /// Sorts signals of every term, merges terms with identical signals and drops zero terms.
+    pub fn simplify(self) -> Self {
+        let mut terms: Vec<(C::F, Vec<C::RawAddr>)> = vec![];
+        for (coeff, mut sigs) in self.terms {
+            sigs.sort();
+            match terms.iter_mut().find(|(_, other)| *other == sigs) {
+                Some(term) => term.0 += coeff,
+                None => terms.push((coeff, sigs)),
+            }
+        }
+        terms.retain(|(coeff, _)| !bool::from(coeff.is_zero()));
+        Constraint { terms, namespace: self.namespace }
+    }
+
+    /// Checks whether the constraint has no terms, i.e. is trivially satisfied.
+    pub fn is_trivial(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Checks whether the constraint is a nonzero constant, i.e. can not be satisfied.
+    pub fn is_unsatisfiable(&self) -> bool {
+        !self.terms.is_empty() && self.terms.iter().all(|(coeff, sigs)| sigs.is_empty() && !bool::from(coeff.is_zero()))
+    }
+}
 
-pub trait Advices : Circuit {
-    fn advise_to_unassigned<I: SVStruct<Self>, O: SVStruct<Self>, F: Fn(I::FStruct) -> O::FStruct>(&mut self, f: F, input: &I, output: &O);
+pub trait ConstraintLog : Circuit + SignalFlag + Namespaces {
+    fn constraints(&self) -> &Vec<Constraint<Self>>;
+    /// Unsafe. Direct access to recorded constraints.
+    fn constraints_mut(&mut self) -> &mut Vec<Constraint<Self>>;
+
+    /// Records the constraint in the current namespace.
+    fn constrain(&mut self, mut constraint: Constraint<Self>) {
+        for (_, sigs) in constraint.terms.iter() {
+            assert!(sigs.iter().all(|sig| self.is_sig(*sig)), "only signals can be constrained");
+        }
+        constraint.namespace = self.namespace();
+        self.constraints_mut().push(constraint)
+    }
+}
+
+// --------- LOOKUPS ---------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableId(pub usize);
+
+#[derive(Clone)]
+pub struct Table<F> {
+    pub name: String,
+    pub columns: usize,
+    pub rows: Vec<Vec<F>>,
+}
+
+/// Constrains a row of signals to be one of the rows of a table.
+pub struct Lookup<C: Circuit> {
+    pub table: TableId,
+    pub sigs: Vec<C::RawAddr>,
+    /// Namespace the lookup was recorded in.
+    pub namespace: String,
+}
+
+impl<C: Circuit> Clone for Lookup<C> {
+    fn clone(&self) -> Self {
+        Self { table: self.table, sigs: self.sigs.clone(), namespace: self.namespace.clone() }
+    }
+}
+
+/// Lookup tables and lookups, recorded alongside the constraints.
+pub trait Lookups : Circuit + SignalFlag + Namespaces {
+    fn tables(&self) -> &Vec<Table<Self::F>>;
+    fn lookups(&self) -> &Vec<Lookup<Self>>;
+    /// Unsafe. Direct access to declared tables.
+    fn tables_mut(&mut self) -> &mut Vec<Table<Self::F>>;
+    /// Unsafe. Direct access to recorded lookups.
+    fn lookups_mut(&mut self) -> &mut Vec<Lookup<Self>>;
+
+    /// Declares a table. Panics if a table with the same name exists, or if some row has wrong width.
+    fn declare_table(&mut self, name: &str, columns: usize, rows: Vec<Vec<Self::F>>) -> TableId {
+        assert!(self.table(name).is_none(), "table {name} is already declared");
+        assert!(rows.iter().all(|row| row.len() == columns), "rows of table {name} must have {columns} columns");
+        self.tables_mut().push(Table { name: name.to_string(), columns, rows });
+        TableId(self.tables().len() - 1)
+    }
+
+    /// Finds a declared table by name.
+    fn table(&self, name: &str) -> Option<TableId> {
+        self.tables().iter().position(|table| table.name == name).map(TableId)
+    }
+
+    /// Constrains (sigs[0], ..., sigs[columns - 1]) to be a row of the table, in the current namespace.
+    fn lookup(&mut self, table: TableId, sigs: &[Sig<Self, Self::F>]) where Self::Config: HasSigtype<Self::F> {
+        let columns = self.tables()[table.0].columns;
+        assert!(sigs.len() == columns, "lookup into table with {columns} columns");
+        let sigs = sigs.iter().map(|sig| sig.to_raw_addr()).collect();
+        let namespace = self.namespace();
+        self.lookups_mut().push(Lookup { table, sigs, namespace })
+    }
+}
 
-    fn advise<I: SVStruct<Self>, O: SVStruct<Self>, F: Fn(I::FStruct) -> O::FStruct>(&mut self, f: F, input: &I) -> O {
+
+// --------- ADVICES ---------
+
+
+/// Advised structs and functions are kept by the circuit (see AdviceLog), hence Clone and 'static.
+pub trait Advices : Circuit + 'static {
+    /// Implementations should be #[track_caller], so recorded advices point to the gadget which called advise.
+    fn advise_to_unassigned<I, O, F>(&mut self, f: F, input: &I, output: &O)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static;
+
+    #[track_caller]
+    fn advise<I, O, F>(&mut self, f: F, input: &I) -> O
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: StaticSVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
         let output = O::alloc_to(self);
         self.advise_to_unassigned(f, input, &output);
         output
     }
+
+    /// Same as advise, for outputs whose size is only known at build time.
+    #[track_caller]
+    fn advise_with<I, O, F>(&mut self, f: F, input: &I, shape: &O::Shape) -> O
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SizedSVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
+        let output = O::alloc_to_with(self, shape);
+        self.advise_to_unassigned(f, input, &output);
+        output
+    }
+
+    /// Same as advise_to_unassigned, for functions capturing values. params have to describe all captured values, so
+    /// that advices of the same function with equal params over equal inputs compute equal outputs (see FuncId).
+    /// Circuits keeping an advice log should record them; by default they are ignored.
+    #[track_caller]
+    fn advise_to_unassigned_with_params<I, O, F>(&mut self, f: F, input: &I, output: &O, params: Vec<String>)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
+        let _ = params;
+        self.advise_to_unassigned(f, input, output)
+    }
+
+    /// Same as advise, for functions capturing values described by params (see advise_to_unassigned_with_params).
+    #[track_caller]
+    fn advise_with_params<I, O, F>(&mut self, f: F, input: &I, params: Vec<String>) -> O
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: StaticSVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
+        let output = O::alloc_to(self);
+        self.advise_to_unassigned_with_params(f, input, &output, params);
+        output
+    }
 }
 
-pub trait TAdvice<C, S, F>
+pub trait TAdvice<C, S>
 where
     C: Circuit,
     S: Storage,
-    F: Fn(C::RawAddr) -> S::RawAddr,
 {
-    fn compile(&self, s: &mut S, mapping: F) -> impl RTAdvice<S>;
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> Box<dyn RTAdvice<S>>;
 }
 
-pub struct Advice<I, DI, O, DO, C, S, F>
+pub struct Advice<I, DI, O, DO, C, S>
 where
     I: SVStruct<C>,
-    I: CompileableStruct<C, S, F, DI>,
+    I: CompileableStruct<C, S, DI>,
     S: AllowsStruct<DI>,
     O: SVStruct<C>,
-    O: CompileableStruct<C, S, F, DO>,
+    O: CompileableStruct<C, S, DO>,
     S: AllowsStruct<DO>,
     C: Circuit,
     S: Storage,
-    F: Fn(C::RawAddr) -> S::RawAddr,
 {
     input: I,
     output: O,
     func: Arc<dyn Fn(<S as AllowsStruct<DI>>::DataSturct) -> <S as AllowsStruct<DO>>::DataSturct>,
-    _pd: PhantomData<(C, F)>,
+    _pd: PhantomData<C>,
 }
 
-impl<I, DI, O, DO, C, S, F> TAdvice<C, S, F> for Advice<I, DI, O, DO, C, S, F>
+impl<I, DI, O, DO, C, S> Advice<I, DI, O, DO, C, S>
 where
     I: SVStruct<C>,
-    I: CompileableStruct<C, S, F, DI>,
+    I: CompileableStruct<C, S, DI>,
     S: AllowsStruct<DI>,
     O: SVStruct<C>,
-    O: CompileableStruct<C, S, F, DO>,
+    O: CompileableStruct<C, S, DO>,
     S: AllowsStruct<DO>,
     C: Circuit,
     S: Storage,
-    F: Clone + Fn(C::RawAddr) -> S::RawAddr,
 {
-    fn compile(&self, s: &mut S, mapping: F) -> impl RTAdvice<S> {
-        RuntimeAdvice {
-            input: self.input.compile(s, mapping.clone()),
+    pub fn new(input: I, output: O, func: Arc<dyn Fn(<S as AllowsStruct<DI>>::DataSturct) -> <S as AllowsStruct<DO>>::DataSturct>) -> Self {
+        Advice { input, output, func, _pd: PhantomData }
+    }
+}
+
+impl<I, DI, O, DO, C, S> TAdvice<C, S> for Advice<I, DI, O, DO, C, S>
+where
+    I: SVStruct<C>,
+    I: CompileableStruct<C, S, DI>,
+    S: AllowsStruct<DI>,
+    O: SVStruct<C>,
+    O: CompileableStruct<C, S, DO>,
+    S: AllowsStruct<DO>,
+    C: Circuit,
+    S: Storage + 'static,
+    DI: 'static,
+    DO: 'static,
+{
+    fn compile(&self, s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> Box<dyn RTAdvice<S>> {
+        Box::new(RuntimeAdvice {
+            input: self.input.compile(s, mapping),
             output: self.output.compile(s, mapping),
             func: self.func.clone(),
-        }
+        })
     }
 }
 
+/// Advice over field elements, as recorded by AdviceLog::_log_advice. Compiles into FieldRuntimeAdvice.
+pub struct FieldAdvice<I, O, C>
+where
+    C: Circuit,
+    I: SVStruct<C>,
+    O: SVStruct<C>,
+{
+    input: I,
+    output: O,
+    func: Arc<dyn Fn(I::FStruct) -> O::FStruct>,
+    _pd: PhantomData<C>,
+}
 
+impl<I, O, C> FieldAdvice<I, O, C>
+where
+    C: Circuit,
+    I: SVStruct<C>,
+    O: SVStruct<C>,
+{
+    pub fn new(input: I, output: O, func: Arc<dyn Fn(I::FStruct) -> O::FStruct>) -> Self {
+        FieldAdvice { input, output, func, _pd: PhantomData }
+    }
+}
+
+impl<I, O, C, S> TAdvice<C, S> for FieldAdvice<I, O, C>
+where
+    C: Circuit + 'static,
+    I: SVStruct<C> + Clone + 'static,
+    O: SVStruct<C> + Clone + 'static,
+    S: Storage + ReaderOf<C::F> + WriterOf<C::F> + 'static,
+{
+    fn compile(&self, _s: &mut S, mapping: &dyn Fn(C::RawAddr) -> S::RawAddr) -> Box<dyn RTAdvice<S>> {
+        Box::new(FieldRuntimeAdvice::<I, O, C, S> {
+            inputs: self.input.raw_addrs().into_iter().map(mapping).collect(),
+            outputs: self.output.raw_addrs().into_iter().map(mapping).collect(),
+            input: self.input.clone(),
+            output: self.output.clone(),
+            func: self.func.clone(),
+            _pd: PhantomData,
+        })
+    }
+}
+
+/// What a circuit records about its advices.
+/// Neither mode changes allocated addresses, flags or constraints, so the shape of the circuit is identical in all modes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildMode {
+    /// Advices are recorded with their closures, circuit can be compiled into an execution graph.
+    Full,
+    /// Only input and output addresses of advices are recorded. Enough for analysis passes and key generation.
+    Shape,
+    /// Advices are not recorded at all.
+    ConstraintsOnly,
+}
+
+pub trait BuildModes : Circuit {
+    fn build_mode(&self) -> BuildMode;
+}
+
+/// Identity of an advised function, so advices with equal ids over equal inputs compute equal outputs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FuncId {
+    pub ty: TypeId,
+    /// Description of the values captured by the function. Empty if it captures nothing.
+    pub params: Vec<String>,
+}
+
+impl FuncId {
+    pub fn of<F: 'static>(params: Vec<String>) -> Self {
+        FuncId { ty: TypeId::of::<F>(), params }
+    }
+}
+
+pub struct AdviceRecord<C: Circuit, S: Storage> {
+    pub inputs: Vec<C::RawAddr>,
+    pub outputs: Vec<C::RawAddr>,
+    /// Identity of the advised function, if it captures nothing or its captured values are described.
+    pub func_id: Option<FuncId>,
+    /// Namespace the advice was recorded in.
+    pub namespace: String,
+    /// Source location of the call which recorded the advice.
+    pub location: &'static Location<'static>,
+    /// None if the circuit is not built in BuildMode::Full.
+    pub advice: Option<Box<dyn TAdvice<C, S>>>,
+}
+
+/// Circuit which keeps record of its advices, to be compiled against storage type Self::Storage.
+pub trait AdviceLog : Advices + BuildModes + Namespaces + Challenges {
+    type Storage: Storage;
+
+    fn advice_log(&self) -> &Vec<AdviceRecord<Self, Self::Storage>>;
+    /// Unsafe. Direct access to recorded advices.
+    fn advice_log_mut(&mut self) -> &mut Vec<AdviceRecord<Self, Self::Storage>>;
+
+    /// Checks the advice (see Challenges::_check_advice) and records it according to the build mode. The closure is
+    /// only stored (and boxed) in BuildMode::Full. Intended to be called from advise_to_unassigned. The function is
+    /// identified by its type if it is zero-sized, i.e. a function item or a closure capturing nothing (or only
+    /// zero-sized values): such a type has a single value, so equal types compute equal outputs from equal inputs.
+    /// This relies on advices being pure; a zero-sized closure reading global or thread-local state must not be
+    /// passed here. Closures capturing values are not identified, unless described by advise_with_params.
+    #[track_caller]
+    fn _log_advice<I, O, F>(&mut self, input: &I, output: &O, func: F)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+        Self::Storage: ReaderOf<Self::F> + WriterOf<Self::F> + 'static,
+    {
+        let func_id = (std::mem::size_of::<F>() == 0).then(|| FuncId::of::<F>(vec![]));
+        self._log_advice_with_id(input, output, func, func_id)
+    }
+
+    /// Same as _log_advice, with explicit identity of the function. Intended to be called from
+    /// advise_to_unassigned_with_params.
+    #[track_caller]
+    fn _log_advice_with_id<I, O, F>(&mut self, input: &I, output: &O, func: F, func_id: Option<FuncId>)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+        Self::Storage: ReaderOf<Self::F> + WriterOf<Self::F> + 'static,
+    {
+        let (input, output) = (input.clone(), output.clone());
+        self._log_record(input.raw_addrs(), output.raw_addrs(), func_id, move || {
+            Box::new(FieldAdvice::new(input, output, Arc::new(func)))
+        })
+    }
+
+    /// Checks the advice (see Challenges::_check_advice) and records it according to the build mode. The compiled
+    /// form is only built in BuildMode::Full. Shared by all ways of recording advices.
+    #[track_caller]
+    fn _log_record(
+        &mut self,
+        inputs: Vec<Self::RawAddr>,
+        outputs: Vec<Self::RawAddr>,
+        func_id: Option<FuncId>,
+        advice: impl FnOnce() -> Box<dyn TAdvice<Self, Self::Storage>>,
+    ) {
+        self._check_advice(&inputs, &outputs);
+        let advice = match self.build_mode() {
+            BuildMode::ConstraintsOnly => return,
+            BuildMode::Shape => None,
+            BuildMode::Full => Some(advice()),
+        };
+        let record = AdviceRecord {
+            inputs,
+            outputs,
+            func_id,
+            namespace: self.namespace(),
+            location: Location::caller(),
+            advice,
+        };
+        self.advice_log_mut().push(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        gadgets::traits::{
+            atoms::{LinearCombination, Pow5},
+            bigint_arith::advise_limbs,
+            nonzeros::Nonzeros,
+            poseidon_permutation::{PoseidonPermutation, PoseidonSponge},
+            sponge::{TSponge, TSpongePrivate},
+        },
+        middleend::compiler::compile,
+        test_utils::{check_constraints, execute, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_simplify() {
+        let constraint: Constraint<TestCircuit> = Constraint {
+            terms: vec![(Fr::from(2), vec![1, 0]), (Fr::from(3), vec![0, 1]), (Fr::one(), vec![2]), (-Fr::one(), vec![2])],
+            namespace: String::new(),
+        };
+        assert_eq!(constraint.simplify().terms, vec![(Fr::from(5), vec![0, 1])]);
+    }
+
+    /// Builds a circuit through the generic advice API and gadgets using it, returns the input.
+    fn build(mode: BuildMode) -> (TestCircuit, TSig) {
+        let mut c = TestCircuit::new(mode);
+        let a = c.alloc_input::<Fr>();
+        let b: TSig = c.advise(|x: Fr| x.square(), &a);
+        let limbs = advise_limbs(&mut c, b, &BigUint::from(16u32), 2);
+        let d: Vec<TSig> = c.in_namespace("swap", |c| c.advise_with(|x: Vec<Fr>| vec![x[1], x[0]], &limbs, &(2, ())));
+        let mut sponge = <PoseidonSponge<TestCircuit> as TSponge<_>>::new(&mut c);
+        sponge.initialize_capacity(&mut c, Fr::from(7));
+        c.in_namespace("poseidon", |c| c.poseidon(vec![a, b, d[0]]));
+        (c, a)
+    }
+
+    #[test]
+    fn test_cheaper_of() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let x = c.alloc_input::<Fr>();
+        // pow5 takes three multiplications, a linear combination a single constraint.
+        let y = c.cheaper_of(|c| c.pow5(x), |c| c.lc(vec![Fr::from(2)], vec![x]));
+        assert_eq!((c.raw_addrs().len(), c.constraints().len(), c.advice_log().len()), (2, 1, 1));
+        let mut built = (0, 0);
+        let z = c.cheaper_of(|c| { built.0 += 1; c.lc(vec![Fr::from(3)], vec![x]) }, |c| { built.1 += 1; c.pow5(x) });
+        assert_eq!(built, (1, 1));
+        assert_eq!((c.raw_addrs().len(), c.constraints().len(), c.advice_log().len()), (3, 2, 2));
+        assert_eq!((y.to_raw_addr(), z.to_raw_addr()), (1, 2));
+        assert_eq!(c.constraints()[1].terms[1].0, -Fr::from(3));
+
+        // Ties go to b.
+        c.cheaper_of(|c| c.lc(vec![Fr::from(4)], vec![x]), |c| c.lc(vec![Fr::from(5)], vec![x]));
+        assert_eq!(c.constraints()[2].terms[1].0, -Fr::from(5));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let x = c.alloc_input::<Fr>();
+        let cp = c.checkpoint();
+        c._set_public_flag(x.to_raw_addr(), true);
+        c.enforce_nonzero(x);
+        assert_eq!(c.cost_since(&cp), CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 });
+
+        let changes = c.rollback(cp);
+        assert!(!c.is_public(x.to_raw_addr()));
+        assert_eq!((c.raw_addrs().len(), c.constraints().len(), c.advice_log().len()), (1, 0, 0));
+
+        c.replay(changes);
+        assert!(c.is_public(x.to_raw_addr()));
+        assert_eq!((c.raw_addrs().len(), c.constraints().len(), c.advice_log().len()), (2, 1, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "poseidon width must be at least 2")]
+    fn test_sponge_width() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        c.poseidon_width = 1;
+        <PoseidonSponge<TestCircuit> as TSponge<_>>::new(&mut c);
+    }
+
+    #[test]
+    fn test_build_modes() {
+        let (full, a) = build(BuildMode::Full);
+        let (shape, _) = build(BuildMode::Shape);
+        let (constraints_only, _) = build(BuildMode::ConstraintsOnly);
+
+        // b, limbs, swap, capacity, and 2 rounds of 3 pow5 (by 3 multiplications) and 3 linear combinations.
+        assert_eq!(full.advice_log().len(), 4 + 2 * (3 * 3 + 3));
+        assert_eq!(shape.advice_log().len(), full.advice_log().len());
+        for (f, s) in full.advice_log().iter().zip(shape.advice_log().iter()) {
+            assert_eq!((&f.inputs, &f.outputs, &f.func_id, &f.namespace), (&s.inputs, &s.outputs, &s.func_id, &s.namespace));
+            assert_eq!(f.location, s.location);
+            assert!(f.advice.is_some() && s.advice.is_none());
+        }
+        assert_eq!(full.advice_log()[2].namespace, "swap");
+        assert_eq!(full.advice_log()[4].namespace, "poseidon");
+        assert!(constraints_only.advice_log().is_empty());
+        for c in [&shape, &constraints_only] {
+            assert_eq!(c.raw_addrs(), full.raw_addrs());
+            assert_eq!(c.constraints().len(), full.constraints().len());
+        }
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&full, &mut s);
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(9));
+        execute(&graph, &mut s);
+        let limbs = &full.advice_log()[1].outputs;
+        assert_eq!(s.get(&mapping[&limbs[0]]), &Fr::from(1));
+        assert_eq!(s.get(&mapping[&limbs[1]]), &Fr::from(5));
+        let swapped = &full.advice_log()[2].outputs;
+        assert_eq!(s.get(&mapping[&swapped[0]]), &Fr::from(5));
+        assert_eq!(s.get(&mapping[&full.advice_log()[3].outputs[0]]), &Fr::from(7));
+        check_constraints(&full, &s, &mapping);
+    }
+
+    #[test]
+    fn test_challenge_dependencies() {
+        let mut c = TestCircuit::new(BuildMode::ConstraintsOnly);
+        let ch = c.alloc_challenge::<Fr>(0);
+        let ch: TSig = c.sig_from_raw_addr(ch.to_raw_addr());
+        let y = c.advise_dependent(&[ch], 1, |x| x)[0];
+        let w = c.advise_dependent(&[y], 1, |x| x)[0];
+        assert_eq!(c.challenge_dependency(w.to_raw_addr()), Some(0));
+        c.group = 1;
+        let z = c.advise_sigs(&[w], 1, |x| x)[0];
+        assert_eq!(c.group(z.to_raw_addr()), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "advice writes to commitment group 0 using a challenge derived after group 0")]
+    fn test_challenge_dependencies_transitive() {
+        let mut c = TestCircuit::new(BuildMode::ConstraintsOnly);
+        let ch = c.alloc_challenge::<Fr>(0);
+        let ch: TSig = c.sig_from_raw_addr(ch.to_raw_addr());
+        let y = c.advise_dependent(&[ch], 1, |x| x)[0];
+        c.advise_sigs(&[y], 1, |x| x);
+    }
+}
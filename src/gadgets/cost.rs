@@ -0,0 +1,143 @@
+use std::{iter::Sum, ops::{Add, AddAssign, Mul}};
+
+use crate::circuit::Circuit;
+
+/// Amount of resources an invocation of a gadget adds to the circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    pub signals: usize,
+    pub constraints: usize,
+    pub advices: usize,
+    pub lookups: usize,
+}
+
+impl CostEstimate {
+    /// Size of the proof-relevant part: signals, constraints and lookups. Advices only affect witness generation.
+    pub fn weight(&self) -> usize {
+        self.signals + self.constraints + self.lookups
+    }
+}
+
+impl Add for CostEstimate {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        CostEstimate {
+            signals: self.signals + rhs.signals,
+            constraints: self.constraints + rhs.constraints,
+            advices: self.advices + rhs.advices,
+            lookups: self.lookups + rhs.lookups,
+        }
+    }
+}
+
+impl AddAssign for CostEstimate {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs
+    }
+}
+
+impl Mul<usize> for CostEstimate {
+    type Output = Self;
+
+    fn mul(self, rhs: usize) -> Self {
+        CostEstimate {
+            signals: self.signals * rhs,
+            constraints: self.constraints * rhs,
+            advices: self.advices * rhs,
+            lookups: self.lookups * rhs,
+        }
+    }
+}
+
+impl Sum for CostEstimate {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(CostEstimate::default(), |acc, x| acc + x)
+    }
+}
+
+/// Estimates the cost of an operation (described by Op) symbolically, without allocating anything.
+/// Implemented by gadget modules for every circuit whose gadget implementation reports its costs,
+/// e.g. `c.cost(&SplitIntoNLimbsOp { primitive_base, packing: 2, num_limbs: 8 })`.
+pub trait Cost<Op> : Circuit {
+    fn cost(&self, op: &Op) -> CostEstimate;
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+    use num_bigint::BigUint;
+
+    use crate::{
+        circuit::{BuildMode, Checkpoints, Inputs},
+        gadgets::traits::{
+            atoms::{LinearCombinationOp, Pow5, Pow5Op},
+            bigint_arith::{Rangecheck, SplitIntoLimbsStrictOp, SplitIntoNLimbsOp},
+            nonzeros::{EnforceNonzeroOp, Nonzeros},
+            poseidon_permutation::{PoseidonPermutation, PoseidonPermutationOp},
+        },
+        test_utils::{TSig, TestCircuit},
+    };
+
+    use super::*;
+
+    /// Compares the estimate with what build actually adds to the circuit, given the amount of inputs.
+    fn check(estimate: CostEstimate, inputs: usize, build: impl FnOnce(&mut TestCircuit, Vec<TSig>)) {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let inputs = (0..inputs).map(|_| c.alloc_input::<Fr>()).collect::<Vec<_>>();
+        let cp = c.checkpoint();
+        build(&mut c, inputs);
+        assert_eq!(estimate, c.cost_since(&cp));
+    }
+
+    #[test]
+    fn test_pow5_cost() {
+        let c = TestCircuit::new(BuildMode::Full);
+        check(c.cost(&Pow5Op), 1, |c, x| { c.pow5(x[0]); });
+    }
+
+    #[test]
+    fn test_poseidon_cost() {
+        let c = TestCircuit::new(BuildMode::Full);
+        let estimate = c.cost(&PoseidonPermutationOp { width: 3 });
+        // 2 full rounds of 3 fifth powers and 3 linear combinations.
+        assert_eq!(estimate, c.cost(&Pow5Op) * 6 + c.cost(&LinearCombinationOp { len: 3 }) * 6);
+        check(estimate, 3, |c, state| { c.poseidon(state); });
+    }
+
+    #[test]
+    fn test_nonzeros_cost() {
+        let c = TestCircuit::new(BuildMode::Full);
+        check(c.cost(&EnforceNonzeroOp), 1, |c, x| c.enforce_nonzero(x[0]));
+    }
+
+    #[test]
+    fn test_split_into_n_limbs_cost() {
+        let c = TestCircuit::new(BuildMode::Full);
+        // Limbs of base 2^16, each packed from 2 primitive limbs of base 2^8.
+        let primitive_base = BigUint::from(1u32 << 8);
+        let estimate = c.cost(&SplitIntoNLimbsOp { primitive_base: primitive_base.clone(), packing: 2, num_limbs: 8 });
+        // 16 primitive limbs advised at once and looked up, then packed by 8 linear combinations.
+        assert_eq!(estimate, CostEstimate { signals: 24, constraints: 8, advices: 9, lookups: 16 });
+        check(estimate, 1, |c, x| { c.split_into_n_limbs(x[0], &primitive_base, 2, 8); });
+    }
+
+    #[test]
+    fn test_split_into_limbs_strict_cost() {
+        let c = TestCircuit::new(BuildMode::Full);
+        // Primitive limbs below the table size take an additional signal and lookup each.
+        let (bound, primitive_base) = (BigUint::from(1u64 << 40), BigUint::from(16u32));
+        let op = SplitIntoLimbsStrictOp { bound: bound.clone(), primitive_base: primitive_base.clone(), packing: 2 };
+        check(c.cost(&op), 1, |c, x| {
+            c.assume(x[0], &bound);
+            c.split_into_limbs_strict(x[0], &primitive_base, 2);
+        });
+    }
+
+    #[test]
+    fn test_weight() {
+        let cost = CostEstimate { signals: 1, constraints: 2, advices: 3, lookups: 4 };
+        assert_eq!(cost.weight(), 7);
+        assert_eq!([cost, cost].into_iter().sum::<CostEstimate>(), cost * 2);
+    }
+}
@@ -1 +1,2 @@
-
+pub mod poseidon;
+pub mod rangecheck;
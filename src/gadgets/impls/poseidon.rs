@@ -0,0 +1,93 @@
+use crate::{
+    circuit::{Circuit, HasSigtype, Sig, Signals},
+    gadgets::{
+        cost::{Cost, CostEstimate},
+        traits::{
+            atoms::{LinearCombination, LinearCombinationOp, Pow5, Pow5Op},
+            poseidon_permutation::{PoseidonConfig, PoseidonPermutationCostImpl, PoseidonPermutationImpl},
+        },
+    },
+};
+
+/// Permutation with the round structure and MDS matrix given by PoseidonConfig: half of the full rounds, then the
+/// partial rounds, then the other half, each round followed by the linear layer. Round constants are not added.
+pub struct DefaultPoseidon;
+
+impl<C> PoseidonPermutationImpl<C> for DefaultPoseidon
+where
+    C: Circuit + Signals + Pow5 + LinearCombination,
+    C::Config: HasSigtype<<C as Circuit>::F> + PoseidonConfig,
+{
+    fn poseidon_permutation(c: &mut C, mut state: Vec<Sig<C, C::F>>) -> Vec<Sig<C, C::F>> {
+        let config = c.config();
+        let (full, partial) = (config.poseidon_full_rounds(), config.poseidon_partial_rounds());
+        let mds = config.poseidon_mds().matrix::<C::F>(state.len());
+        for round in 0..full + partial {
+            let is_partial = round >= full / 2 && round < full / 2 + partial;
+            for (i, x) in state.iter_mut().enumerate() {
+                if i == 0 || !is_partial {
+                    *x = c.pow5(*x);
+                }
+            }
+            state = mds.iter().map(|row| c.lc(row.clone(), state.clone())).collect();
+        }
+        state
+    }
+}
+
+impl<C> PoseidonPermutationCostImpl<C> for DefaultPoseidon
+where
+    C: Circuit + Signals + Pow5 + LinearCombination + Cost<Pow5Op> + Cost<LinearCombinationOp>,
+    C::Config: HasSigtype<<C as Circuit>::F> + PoseidonConfig,
+{
+    fn poseidon_permutation_cost(c: &C, width: usize) -> CostEstimate {
+        let (full, partial) = (c.config().poseidon_full_rounds(), c.config().poseidon_partial_rounds());
+        let pow5s = full * width + partial;
+        let lcs = (full + partial) * width;
+        c.cost(&Pow5Op) * pow5s + c.cost(&LinearCombinationOp { len: width }) * lcs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, ConstraintLog, Inputs, ToRawAddr},
+        gadgets::traits::poseidon_permutation::{MdsChoice, PoseidonPermutation},
+        middleend::compiler::compile,
+        test_utils::{check_constraints, execute, TestCircuit, TestStorage},
+    };
+
+    /// Permutes (1, 2, 3) in a circuit built with the given configuration.
+    fn permute(configure: impl FnOnce(&mut TestCircuit)) -> (Vec<Fr>, usize) {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        configure(&mut c);
+        let inputs = (0..3).map(|_| c.alloc_input::<Fr>()).collect::<Vec<_>>();
+        let outputs = c.poseidon(inputs.clone());
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        for (i, input) in inputs.iter().enumerate() {
+            s.put(&mapping[&input.to_raw_addr()], Fr::from(i as u64 + 1));
+        }
+        execute(&graph, &mut s);
+        check_constraints(&c, &s, &mapping);
+        let values = outputs.iter().map(|sig| *s.get(&mapping[&sig.to_raw_addr()])).collect();
+        (values, c.constraints().len())
+    }
+
+    #[test]
+    fn test_configurations() {
+        let (cauchy, constraints) = permute(|_| {});
+        let (generated, _) = permute(|c| c.poseidon_mds = MdsChoice::Generated(7));
+        let (rounds, more_constraints) = permute(|c| (c.poseidon_full_rounds, c.poseidon_partial_rounds) = (4, 2));
+        assert_ne!(cauchy, generated);
+        assert_ne!(cauchy, rounds);
+        // Fifth powers take 3 constraints each: 6 of them and 6 linear combinations in 2 full rounds, 14 and 18 in 4
+        // full rounds and 2 partial ones.
+        assert_eq!(constraints, 6 * 3 + 6);
+        assert_eq!(more_constraints, 14 * 3 + 18);
+    }
+}
@@ -0,0 +1,177 @@
+use ff::{Field, PrimeField};
+use num_bigint::BigUint;
+
+use crate::{
+    circuit::{Advices, Circuit, Constraint, ConstraintLog, HasSigtype, Lookups, RangeBound, Sig, Signals, ToRawAddr, _Into},
+    gadgets::{
+        cost::CostEstimate,
+        traits::bigint_arith::{advise_limbs, range_table, RangecheckConfig, RangecheckCostImpl, RangecheckImpl},
+    },
+};
+
+/// Range checks by lookups into range_table(c, lookup_table_size), bounds are kept in RangeBound. Primitive checks
+/// accept bounds up to rangecheck_primitive_base, which must not exceed the table size.
+pub struct LookupRangecheck;
+
+fn to_biguint<F: PrimeField>(x: &F) -> BigUint {
+    BigUint::from_bytes_le(x.to_repr().as_ref())
+}
+
+fn from_biguint<F: PrimeField>(x: &BigUint) -> F {
+    F::from_str_vartime(&x.to_str_radix(10)).unwrap()
+}
+
+impl<C> RangecheckImpl<C> for LookupRangecheck
+where
+    C: Circuit + Signals + Advices + ConstraintLog + Lookups + RangeBound,
+    C::Config: HasSigtype<<C as Circuit>::F> + RangecheckConfig,
+{
+    fn bound(c: &C, sig: Sig<C, C::F>) -> Option<BigUint> {
+        RangeBound::bound(c, sig.to_raw_addr())
+    }
+
+    fn assume(c: &mut C, sig: Sig<C, C::F>, bound: &BigUint) {
+        let modulus = to_biguint(&-C::F::ONE) + 1u32;
+        assert!(bound <= &modulus, "bound {bound} exceeds the field modulus");
+        match RangeBound::bound(c, sig.to_raw_addr()) {
+            Some(old) if &old <= bound => {}
+            _ => c._set_bound(sig.to_raw_addr(), Some(bound)),
+        }
+    }
+
+    fn num_linear_combination(c: &mut C, coeffs: &[BigUint], values: &[Sig<C, C::F>]) -> Sig<C, C::F> {
+        let max: BigUint = coeffs.iter().zip(values)
+            .map(|(coeff, sig)| {
+                let bound = Self::bound(c, *sig).expect("linear combination of an unbounded signal");
+                coeff * (bound - 1u32)
+            })
+            .sum();
+        let k: Vec<C::F> = coeffs.iter().map(from_biguint).collect();
+        let params = coeffs.iter().map(|coeff| coeff.to_string()).collect();
+        let weights = k.clone();
+        let t: Sig<C, C::F> = c.advise_with_params(
+            move |x: Vec<C::F>| x.iter().zip(weights.iter()).map(|(x, k)| *x * k).sum(),
+            &values.to_vec(),
+            params,
+        );
+        let mut constraint = Constraint::new().term(C::F::ONE, &[t._into()]);
+        for (k, sig) in k.into_iter().zip(values) {
+            constraint = constraint.term(-k, &[(*sig)._into()]);
+        }
+        c.constrain(constraint);
+        Self::assume(c, t, &(max + 1u32));
+        t
+    }
+
+    fn num_mul(c: &mut C, a: Sig<C, C::F>, b: Sig<C, C::F>) -> Sig<C, C::F> {
+        let bound_a = Self::bound(c, a).expect("product of an unbounded signal");
+        let bound_b = Self::bound(c, b).expect("product of an unbounded signal");
+        let t: Sig<C, C::F> = c.advise(|x: Vec<C::F>| x[0] * x[1], &vec![a, b]);
+        c.constrain(Constraint::new().term(C::F::ONE, &[t._into()]).term(-C::F::ONE, &[a._into(), b._into()]));
+        Self::assume(c, t, &((bound_a - 1u32) * (bound_b - 1u32) + 1u32));
+        t
+    }
+
+    fn max_primitive_rangecheck(c: &C) -> usize {
+        c.config().rangecheck_primitive_base()
+    }
+
+    /// Looks up sig, and for bounds below the table size also sig + (size - bound).
+    fn primitive_rangecheck(c: &mut C, sig: Sig<C, C::F>, bound: &BigUint) {
+        let max = Self::max_primitive_rangecheck(c);
+        let size = c.config().lookup_table_size();
+        assert!(max <= size, "primitive rangecheck base {max} exceeds lookup table size {size}");
+        assert!(bound <= &BigUint::from(max), "bound {bound} exceeds primitive rangecheck base {max}");
+        let table = range_table(c, size);
+        c.lookup(table, &[sig]);
+        if bound < &BigUint::from(size) {
+            let shift = BigUint::from(size) - bound;
+            let params = vec![shift.to_string()];
+            let shift = from_biguint::<C::F>(&shift);
+            let shifted: Sig<C, C::F> = c.advise_with_params(move |x: C::F| x + shift, &sig, params);
+            let constraint = Constraint::new().term(C::F::ONE, &[shifted._into()]).term(-C::F::ONE, &[sig._into()]);
+            c.constrain(constraint.term(-shift, &[]));
+            c.lookup(table, &[shifted]);
+        }
+        Self::assume(c, sig, bound);
+    }
+
+    fn advise_split_into_n_limbs(c: &mut C, sig: Sig<C, C::F>, base: &BigUint, num_limbs: u32) -> Vec<Sig<C, C::F>> {
+        advise_limbs(c, sig, base, num_limbs)
+    }
+}
+
+impl<C> RangecheckCostImpl<C> for LookupRangecheck
+where
+    C: Circuit + Signals + Advices + ConstraintLog + Lookups + RangeBound,
+    C::Config: HasSigtype<<C as Circuit>::F> + RangecheckConfig,
+{
+    fn num_linear_combination_cost(_c: &C, _len: usize) -> CostEstimate {
+        CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 }
+    }
+
+    fn num_mul_cost(_c: &C) -> CostEstimate {
+        CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 }
+    }
+
+    fn primitive_rangecheck_cost(c: &C, bound: &BigUint) -> CostEstimate {
+        if bound < &BigUint::from(c.config().lookup_table_size()) {
+            CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 2 }
+        } else {
+            CostEstimate { signals: 0, constraints: 0, advices: 0, lookups: 1 }
+        }
+    }
+
+    fn advise_split_into_n_limbs_cost(_c: &C, _base: &BigUint, num_limbs: u32) -> CostEstimate {
+        CostEstimate { signals: num_limbs as usize, constraints: 0, advices: 1, lookups: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::WriterOf,
+        circuit::{BuildMode, Inputs, ToRawAddr},
+        gadgets::traits::bigint_arith::Rangecheck,
+        middleend::compiler::compile,
+        test_utils::{check_constraints, execute, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    /// Splits 0xabc into 3 limbs of base 16 in a circuit with the given primitive base and table size, returns the
+    /// table sizes and the amount of lookups.
+    fn split(base: usize, table_size: usize) -> (Vec<usize>, usize) {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        (c.rangecheck_base, c.lookup_table_size) = (base, table_size);
+        let x = c.alloc_input::<Fr>();
+        let limbs = c.split_into_n_limbs(x, &BigUint::from(16u32), 1, 3);
+        assert_eq!(Rangecheck::bound(&c, x), Some(BigUint::from(0x1000u32)));
+        assert!(limbs.iter().all(|limb| Rangecheck::bound(&c, *limb) == Some(BigUint::from(16u32))));
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        s.put(&mapping[&x.to_raw_addr()], Fr::from(0xabc));
+        execute(&graph, &mut s);
+        check_constraints(&c, &s, &mapping);
+        (c.tables().iter().map(|table| table.rows.len()).collect(), c.lookups().len())
+    }
+
+    #[test]
+    fn test_configurations() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        c.rangecheck_base = 8;
+        assert_eq!(c.max_primitive_rangecheck(), 8);
+        // Limbs fill the whole table, or are shifted into it by an additional lookup each.
+        assert_eq!(split(16, 16), (vec![16], 3));
+        assert_eq!(split(16, 256), (vec![256], 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds lookup table size")]
+    fn test_base_exceeds_table() {
+        split(256, 16);
+    }
+}
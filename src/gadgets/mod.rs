@@ -1,3 +1,4 @@
 pub mod constraints_lib;
+pub mod cost;
 pub mod impls;
 pub mod traits;
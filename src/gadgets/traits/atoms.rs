@@ -1,11 +1,31 @@
-use crate::circuit::{Circuit, HasSigtype, Sig, Signals};
+use crate::{circuit::{Circuit, HasSigtype, Sig, Signals}, gadgets::cost::{Cost, CostEstimate}};
+
+pub trait Pow5Impl<C>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    fn pow5(c: &mut C, i: Sig<C, C::F>) -> Sig<C, C::F>;
+}
 
 pub trait Pow5
 where
     Self: Circuit + Signals,
     Self::Config: HasSigtype<<Self as Circuit>::F>,
 {
-    fn pow5(&mut self, i: Sig<Self, Self::F>) -> Sig<Self, Self::F>;
+    type IPow5: Pow5Impl<Self>;
+
+    fn pow5(&mut self, i: Sig<Self, Self::F>) -> Sig<Self, Self::F> {
+        Self::IPow5::pow5(self, i)
+    }
+}
+
+pub trait LinearCombinationImpl<C>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    fn lc(c: &mut C, coeffs: Vec<C::F>, sigs: Vec<Sig<C, C::F>>) -> Sig<C, C::F>;
 }
 
 pub trait LinearCombination
@@ -13,5 +33,54 @@ where
     Self: Circuit + Signals,
     Self::Config: HasSigtype<<Self as Circuit>::F>,
 {
-    fn lc(&mut self, coeffs: Vec<Self::F>, sigs: Vec<Sig<Self, Self::F>>) -> Sig<Self, Self::F>;
+    type ILinearCombination: LinearCombinationImpl<Self>;
+
+    fn lc(&mut self, coeffs: Vec<Self::F>, sigs: Vec<Sig<Self, Self::F>>) -> Sig<Self, Self::F> {
+        Self::ILinearCombination::lc(self, coeffs, sigs)
+    }
+}
+
+pub trait Pow5CostImpl<C> : Pow5Impl<C>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    fn pow5_cost(c: &C) -> CostEstimate;
+}
+
+pub trait LinearCombinationCostImpl<C> : LinearCombinationImpl<C>
+where
+    C: Circuit + Signals,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    /// Cost of a linear combination of len signals.
+    fn lc_cost(c: &C, len: usize) -> CostEstimate;
+}
+
+pub struct Pow5Op;
+
+pub struct LinearCombinationOp {
+    pub len: usize,
+}
+
+impl<C> Cost<Pow5Op> for C
+where
+    C: Pow5,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IPow5: Pow5CostImpl<C>,
+{
+    fn cost(&self, _: &Pow5Op) -> CostEstimate {
+        C::IPow5::pow5_cost(self)
+    }
+}
+
+impl<C> Cost<LinearCombinationOp> for C
+where
+    C: LinearCombination,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::ILinearCombination: LinearCombinationCostImpl<C>,
+{
+    fn cost(&self, op: &LinearCombinationOp) -> CostEstimate {
+        C::ILinearCombination::lc_cost(self, op.len)
+    }
 }
@@ -1,6 +1,15 @@
+use ff::PrimeField;
 use num_bigint::BigUint;
 
-use crate::circuit::{Circuit, HasSigtype, Sig};
+use crate::{circuit::{Advices, Circuit, HasSigtype, Lookups, Sig, Signals, TableId}, gadgets::cost::{Cost, CostEstimate}};
+
+/// Runtime parameters of range checks, carried by Circuit::Config.
+pub trait RangecheckConfig {
+    /// Base of a primitive rangecheck, i.e. the value max_primitive_rangecheck is expected to report.
+    fn rangecheck_primitive_base(&self) -> usize;
+    /// Amount of rows in lookup tables backing primitive rangechecks.
+    fn lookup_table_size(&self) -> usize;
+}
 
 pub trait RangecheckImpl<C>
 where
@@ -23,12 +32,14 @@ where
 
     /// Returns the maximal bound that is achievable in a single check.
     /// Check of sizes lesser than this MIGHT be implemented as two checks on x and x-k.
+    /// Implementations configurable at runtime should report RangecheckConfig::rangecheck_primitive_base.
     fn max_primitive_rangecheck(c: &C) -> usize;
 
     /// Range-checks the signal. Fails if bound > max_primitive_rangecheck
     fn primitive_rangecheck(c: &mut C, sig: Sig<C, C::F>, bound: &BigUint);
 
     /// Splits the value into limbs with some base. Does not constrain anything.
+    /// See advise_limbs for a generic implementation.
     fn advise_split_into_n_limbs(c: &mut C, sig: Sig<C, C::F>, base: &BigUint, num_limbs: u32) -> Vec<Sig<C, C::F>>;
 
     /// Splits the signal into limbs (and range-checks it). Each limb is combined from #packing primitive limbs.
@@ -74,7 +85,7 @@ where
     }
 
     /// Attempts to flush the registers. Might fail if bounds are too large.
-    /// WARNING: this implementation does not optimize linear constraints; optimally this should be done by backend.
+    /// This implementation does not optimize linear constraints; they can be substituted away by middleend::linear_elim.
     fn normalize(c: &mut C, limbs: &Vec<Sig<C, C::F>>, primitive_base: &BigUint, packing: u32) -> Vec<Sig<C, C::F>> {
         let mut limbs : Vec<_> = limbs.into_iter().map(|x|vec![*x]).collect();
         let mut i = 0;
@@ -107,6 +118,72 @@ where
     }
 }
 
+/// Costs of RangecheckImpl operations. Composite costs mirror the default implementations of RangecheckImpl,
+/// and should be overriden together with them.
+pub trait RangecheckCostImpl<C> : RangecheckImpl<C>
+where
+    C: Circuit,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    /// Cost of a non-negative linear combination of len signals.
+    fn num_linear_combination_cost(c: &C, len: usize) -> CostEstimate;
+
+    fn num_mul_cost(c: &C) -> CostEstimate;
+
+    fn primitive_rangecheck_cost(c: &C, bound: &BigUint) -> CostEstimate;
+
+    fn advise_split_into_n_limbs_cost(c: &C, base: &BigUint, num_limbs: u32) -> CostEstimate;
+
+    fn split_into_n_limbs_cost(c: &C, primitive_base: &BigUint, packing: u32, num_limbs: u32) -> CostEstimate {
+        let num_primitive_limbs = num_limbs * packing;
+        Self::advise_split_into_n_limbs_cost(c, primitive_base, num_primitive_limbs)
+            + Self::primitive_rangecheck_cost(c, primitive_base) * num_primitive_limbs as usize
+            + Self::num_linear_combination_cost(c, packing as usize) * num_limbs as usize
+    }
+
+    /// Cost of splitting a signal with a given bound.
+    fn split_into_limbs_strict_cost(c: &C, bound: &BigUint, primitive_base: &BigUint, packing: u32) -> CostEstimate {
+        let base = primitive_base.pow(packing);
+        let num_limbs = log_ceil(&base, bound);
+        Self::split_into_n_limbs_cost(c, primitive_base, packing, num_limbs)
+    }
+}
+
+/// Splits the value into num_limbs limbs in given base, least significant first, dropping the rest of it.
+/// Assumes little-endian field representation.
+pub fn split_limbs<F: PrimeField>(x: &F, base: &BigUint, num_limbs: u32) -> Vec<F> {
+    let mut x = BigUint::from_bytes_le(x.to_repr().as_ref());
+    (0..num_limbs).map(|_| {
+        let limb = &x % base;
+        x /= base;
+        F::from_str_vartime(&limb.to_str_radix(10)).unwrap()
+    }).collect()
+}
+
+/// Advises num_limbs limbs of the value in given base, least significant first (see split_limbs). Does not constrain
+/// anything. Generic implementation of RangecheckImpl::advise_split_into_n_limbs.
+pub fn advise_limbs<C>(c: &mut C, sig: Sig<C, C::F>, base: &BigUint, num_limbs: u32) -> Vec<Sig<C, C::F>>
+where
+    C: Circuit + Signals + Advices,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    let base = base.clone();
+    c.advise_with(move |x: C::F| split_limbs(&x, &base, num_limbs), &sig, &(num_limbs as usize, ()))
+}
+
+/// Returns the table of values 0..size, declaring it on first use.
+/// Intended for primitive rangechecks by lookup, with size = RangecheckConfig::lookup_table_size.
+pub fn range_table<C: Lookups>(c: &mut C, size: usize) -> TableId {
+    let name = format!("range_{size}");
+    match c.table(&name) {
+        Some(id) => id,
+        None => {
+            let rows = (0..size as u64).map(|i| vec![i.into()]).collect();
+            c.declare_table(&name, 1, rows)
+        }
+    }
+}
+
 /// Returns ceil(log_b(x)).
 /// Can be used to compute amount of limbs of base b necessary to hold any value in 0..x.
 fn log_ceil(b: &BigUint, x: &BigUint) -> u32 {
@@ -196,4 +273,157 @@ fn log_ceil(b: &BigUint, x: &BigUint) -> u32 {
         Self::IRangecheck::split_into_limbs_strict(self, sig, primitive_base, packing)
     }
 
- }
\ No newline at end of file
+ }
+
+pub struct NumLinearCombinationOp {
+    pub len: usize,
+}
+
+pub struct NumMulOp;
+
+pub struct PrimitiveRangecheckOp {
+    pub bound: BigUint,
+}
+
+pub struct AdviseSplitIntoNLimbsOp {
+    pub base: BigUint,
+    pub num_limbs: u32,
+}
+
+pub struct SplitIntoNLimbsOp {
+    pub primitive_base: BigUint,
+    pub packing: u32,
+    pub num_limbs: u32,
+}
+
+pub struct SplitIntoLimbsStrictOp {
+    /// Bound of the signal being split.
+    pub bound: BigUint,
+    pub primitive_base: BigUint,
+    pub packing: u32,
+}
+
+impl<C> Cost<NumLinearCombinationOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, op: &NumLinearCombinationOp) -> CostEstimate {
+        C::IRangecheck::num_linear_combination_cost(self, op.len)
+    }
+}
+
+impl<C> Cost<NumMulOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, _: &NumMulOp) -> CostEstimate {
+        C::IRangecheck::num_mul_cost(self)
+    }
+}
+
+impl<C> Cost<PrimitiveRangecheckOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, op: &PrimitiveRangecheckOp) -> CostEstimate {
+        C::IRangecheck::primitive_rangecheck_cost(self, &op.bound)
+    }
+}
+
+impl<C> Cost<AdviseSplitIntoNLimbsOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, op: &AdviseSplitIntoNLimbsOp) -> CostEstimate {
+        C::IRangecheck::advise_split_into_n_limbs_cost(self, &op.base, op.num_limbs)
+    }
+}
+
+impl<C> Cost<SplitIntoNLimbsOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, op: &SplitIntoNLimbsOp) -> CostEstimate {
+        C::IRangecheck::split_into_n_limbs_cost(self, &op.primitive_base, op.packing, op.num_limbs)
+    }
+}
+
+impl<C> Cost<SplitIntoLimbsStrictOp> for C
+where
+    C: Rangecheck,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::IRangecheck: RangecheckCostImpl<C>,
+{
+    fn cost(&self, op: &SplitIntoLimbsStrictOp) -> CostEstimate {
+        C::IRangecheck::split_into_limbs_strict_cost(self, &op.bound, &op.primitive_base, op.packing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{AdviceLog, BuildMode, Inputs, ToRawAddr},
+        middleend::compiler::compile,
+        test_utils::{execute, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    /// Computes num_limbs limbs of x in given base by the compiled advice.
+    fn limbs(x: Fr, base: &BigUint, num_limbs: u32) -> Vec<Fr> {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let limbs = advise_limbs(&mut c, a, base, num_limbs);
+        assert_eq!(limbs.len(), num_limbs as usize);
+        assert_eq!(c.advice_log().len(), 1);
+        assert_eq!(c.advice_log()[0].inputs, vec![a.to_raw_addr()]);
+        assert_eq!(c.advice_log()[0].outputs, limbs.iter().map(|limb| limb.to_raw_addr()).collect::<Vec<_>>());
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        s.put(&mapping[&a.to_raw_addr()], x);
+        execute(&graph, &mut s);
+        limbs.iter().map(|limb| *s.get(&mapping[&limb.to_raw_addr()])).collect()
+    }
+
+    #[test]
+    fn test_advise_limbs() {
+        let base = BigUint::from(16u32);
+        assert_eq!(limbs(Fr::from(0x1234), &base, 4), [4, 3, 2, 1].map(Fr::from));
+        assert_eq!(limbs(Fr::from(0x12), &base, 4), [2, 1, 0, 0].map(Fr::from));
+        assert_eq!(limbs(Fr::ZERO, &base, 3), [Fr::ZERO; 3]);
+        assert!(limbs(Fr::from(7), &base, 0).is_empty());
+    }
+
+    #[test]
+    fn test_advise_limbs_truncated() {
+        // Limbs above num_limbs are dropped, the split is only checked by the constraints of the caller.
+        assert_eq!(limbs(Fr::from(0x12345), &BigUint::from(16u32), 4), [5, 4, 3, 2].map(Fr::from));
+    }
+
+    #[test]
+    fn test_advise_limbs_large_base() {
+        // -1 = p - 1 in 64-bit limbs, i.e. the little-endian words of the modulus with the lowest one decremented.
+        let base = BigUint::from(1u32) << 64;
+        let expected: Vec<Fr> = BigUint::from_bytes_le((-Fr::ONE).to_repr().as_ref())
+            .to_u64_digits()
+            .into_iter()
+            .map(Fr::from)
+            .collect();
+        assert_eq!(limbs(-Fr::ONE, &base, 4), expected);
+    }
+}
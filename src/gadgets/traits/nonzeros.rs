@@ -1,4 +1,4 @@
-use crate::circuit::{Circuit, HasSigtype, Sig};
+use crate::{circuit::{Circuit, HasSigtype, Sig}, gadgets::cost::{Cost, CostEstimate}};
 
 pub trait NonzerosImpl<C>
 where
@@ -19,3 +19,24 @@ where
         Self::INonzeros::enforce_nonzero(self, x);
     }
 }
+
+pub trait NonzerosCostImpl<C> : NonzerosImpl<C>
+where
+    C: Circuit,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    fn enforce_nonzero_cost(c: &C) -> CostEstimate;
+}
+
+pub struct EnforceNonzeroOp;
+
+impl<C> Cost<EnforceNonzeroOp> for C
+where
+    C: Nonzeros,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::INonzeros: NonzerosCostImpl<C>,
+{
+    fn cost(&self, _: &EnforceNonzeroOp) -> CostEstimate {
+        C::INonzeros::enforce_nonzero_cost(self)
+    }
+}
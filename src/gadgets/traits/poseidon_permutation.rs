@@ -1,8 +1,63 @@
+use ff::PrimeField;
+
 use crate::circuit::{Advices, Circuit, HasSigtype, Sig, Signals, Variables};
+use crate::gadgets::cost::{Cost, CostEstimate};
 
 use super::sponge::{TSpongePrivate, TSponge, SpongeAction};
 use super::atoms::{Pow5, LinearCombination};
 
+/// Selects the MDS matrix of the linear layer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MdsChoice {
+    /// Cauchy matrix 1/(x_i + y_j) with x_i = i, y_j = width + j.
+    Cauchy,
+    /// Cauchy matrix over 2 * width distinct points drawn from a splitmix64 stream with the given seed.
+    Generated(u64),
+}
+
+impl MdsChoice {
+    /// Returns the width x width matrix of the linear layer.
+    pub fn matrix<F: PrimeField>(&self, width: usize) -> Vec<Vec<F>> {
+        let points: Vec<u64> = match *self {
+            MdsChoice::Cauchy => (0..2 * width as u64).collect(),
+            MdsChoice::Generated(seed) => {
+                let mut state = seed;
+                let mut points = Vec::with_capacity(2 * width);
+                while points.len() < 2 * width {
+                    let x = splitmix64(&mut state);
+                    if !points.contains(&x) {
+                        points.push(x);
+                    }
+                }
+                points
+            }
+        };
+        let (xs, ys) = points.split_at(width);
+        xs.iter()
+            .map(|x| ys.iter().map(|y| (F::from(*x) + F::from(*y)).invert().unwrap()).collect())
+            .collect()
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Runtime parameters of the Poseidon permutation, carried by Circuit::Config.
+pub trait PoseidonConfig {
+    /// State width t = rate + capacity, at least 2.
+    fn poseidon_width(&self) -> usize;
+    /// Rounds applying the S-box to the whole state, half of them before the partial rounds and half after.
+    fn poseidon_full_rounds(&self) -> usize;
+    /// Rounds applying the S-box to the first element of the state only.
+    fn poseidon_partial_rounds(&self) -> usize;
+    fn poseidon_mds(&self) -> MdsChoice;
+}
+
 pub struct PoseidonSponge<C>
 where
     C: Circuit + Signals + Variables,
@@ -86,17 +141,21 @@ where
     }
 
     fn initialize_capacity(&mut self, c: &mut C, capacity: Self::Field) {
-        c.advise_to_unassigned(|_| capacity, &(), &self.initial_capacity)
+        c.advise_to_unassigned(move |_| capacity, &(), &self.initial_capacity)
     }
 }
 
 impl<C> TSponge<C> for PoseidonSponge<C>
 where
     C: Circuit + PoseidonPermutation + Signals + Advices,
-    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::Config: HasSigtype<<C as Circuit>::F> + PoseidonConfig,
 {
+    /// Creates a sponge of the width configured for this circuit, with a single capacity element.
     fn new(c: &mut C) -> Self {
-        todo!()
+        let width = c.config().poseidon_width();
+        assert!(width >= 2, "poseidon width must be at least 2, got {width}");
+        let rate = width - 1;
+        <Self as TSpongePrivate<C>>::new(c, 0, rate)
     }
 }
 
@@ -118,3 +177,27 @@ where
         Self::ImplInstance::poseidon_permutation(self, inputs)
     }
 }
+
+pub trait PoseidonPermutationCostImpl<C> : PoseidonPermutationImpl<C>
+where
+    C: Circuit + Signals + Pow5 + LinearCombination,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+{
+    /// Cost of a single permutation of a state of given width.
+    fn poseidon_permutation_cost(c: &C, width: usize) -> CostEstimate;
+}
+
+pub struct PoseidonPermutationOp {
+    pub width: usize,
+}
+
+impl<C> Cost<PoseidonPermutationOp> for C
+where
+    C: PoseidonPermutation,
+    C::Config: HasSigtype<<C as Circuit>::F>,
+    C::ImplInstance: PoseidonPermutationCostImpl<C>,
+{
+    fn cost(&self, op: &PoseidonPermutationOp) -> CostEstimate {
+        C::ImplInstance::poseidon_permutation_cost(self, op.width)
+    }
+}
@@ -1,3 +1,40 @@
+// Lets macro-generated `::zk_frontend::...` paths resolve inside this crate too.
+extern crate self as zk_frontend;
+
 pub mod backend;
 pub mod circuit;
 pub mod gadgets;
+pub mod middleend;
+
+#[cfg(test)]
+mod test_utils;
+
+pub use macros::impl_gadgets;
+
+#[cfg(test)]
+mod tests {
+    use std::any::TypeId;
+
+    use crate::{
+        gadgets::{
+            impls::{poseidon::DefaultPoseidon, rangecheck::LookupRangecheck},
+            traits::{
+                atoms::{LinearCombination, Pow5},
+                bigint_arith::Rangecheck,
+                nonzeros::Nonzeros,
+                poseidon_permutation::PoseidonPermutation,
+            },
+        },
+        test_utils::{AdvisedLc, InverseNonzeros, MulPow5, TestCircuit},
+    };
+
+    #[test]
+    fn test_impl_gadgets() {
+        // TestCircuit lists Lc and Poseidon by their short names.
+        assert_eq!(TypeId::of::<<TestCircuit as Pow5>::IPow5>(), TypeId::of::<MulPow5>());
+        assert_eq!(TypeId::of::<<TestCircuit as LinearCombination>::ILinearCombination>(), TypeId::of::<AdvisedLc>());
+        assert_eq!(TypeId::of::<<TestCircuit as PoseidonPermutation>::ImplInstance>(), TypeId::of::<DefaultPoseidon>());
+        assert_eq!(TypeId::of::<<TestCircuit as Rangecheck>::IRangecheck>(), TypeId::of::<LookupRangecheck>());
+        assert_eq!(TypeId::of::<<TestCircuit as Nonzeros>::INonzeros>(), TypeId::of::<InverseNonzeros>());
+    }
+}
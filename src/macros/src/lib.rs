@@ -1,5 +1,12 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Generics, Ident, Token, Type,
+};
 
 #[proc_macro]
 pub fn make_tuple_impls(_item: TokenStream) -> TokenStream {
@@ -125,3 +132,95 @@ pub fn make_tuple_impls(_item: TokenStream) -> TokenStream {
 
     buf.parse().unwrap()
 }
+
+/// Gadget traits known to `impl_gadgets!`: accepted keys, path of the trait inside `zk_frontend`
+/// and the associated type selecting its implementation.
+const GADGETS: &[(&[&str], &str, &str)] = &[
+    (&["Pow5"], "gadgets::traits::atoms::Pow5", "IPow5"),
+    (&["LinearCombination", "Lc"], "gadgets::traits::atoms::LinearCombination", "ILinearCombination"),
+    (&["Poseidon", "PoseidonPermutation"], "gadgets::traits::poseidon_permutation::PoseidonPermutation", "ImplInstance"),
+    (&["Rangecheck"], "gadgets::traits::bigint_arith::Rangecheck", "IRangecheck"),
+    (&["Nonzeros"], "gadgets::traits::nonzeros::Nonzeros", "INonzeros"),
+];
+
+struct GadgetBinding {
+    gadget: Ident,
+    instance: Type,
+}
+
+impl Parse for GadgetBinding {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let gadget = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let instance = input.parse()?;
+        Ok(GadgetBinding { gadget, instance })
+    }
+}
+
+struct ImplGadgets {
+    generics: Generics,
+    circuit: Type,
+    bindings: Punctuated<GadgetBinding, Token![,]>,
+}
+
+impl Parse for ImplGadgets {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let generics = if input.peek(Token![impl]) {
+            input.parse::<Token![impl]>()?;
+            input.parse()?
+        } else {
+            Generics::default()
+        };
+        let circuit = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let bindings = Punctuated::parse_terminated(input)?;
+        Ok(ImplGadgets { generics, circuit, bindings })
+    }
+}
+
+/// Wires gadget implementations into a circuit type.
+///
+/// `impl_gadgets!(MyCircuit: Poseidon = DefaultPoseidon, Rangecheck = LookupRangecheck)`
+/// expands into `impl PoseidonPermutation for MyCircuit { type ImplInstance = DefaultPoseidon; }`
+/// and so on for every listed gadget. Generic circuits are written as
+/// `impl_gadgets!(impl<F: PrimeField> MyCircuit<F>: Pow5 = ...)`.
+///
+/// Supertraits are not inferred: a gadget composed from other gadgets (e.g. Poseidon needs Pow5 and
+/// LinearCombination) requires them to be listed as well.
+#[proc_macro]
+pub fn impl_gadgets(item: TokenStream) -> TokenStream {
+    let ImplGadgets { generics, circuit, bindings } = parse_macro_input!(item as ImplGadgets);
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+    let mut seen: Vec<&str> = vec![];
+    let mut impls = vec![];
+    for binding in bindings.iter() {
+        let key = binding.gadget.to_string();
+        let Some((keys, path, assoc)) = GADGETS.iter().find(|(keys, _, _)| keys.contains(&key.as_str())) else {
+            let known: Vec<&str> = GADGETS.iter().map(|(keys, _, _)| keys[0]).collect();
+            return syn::Error::new(
+                binding.gadget.span(),
+                format!("unknown gadget `{key}`, expected one of: {}", known.join(", ")),
+            )
+            .to_compile_error()
+            .into();
+        };
+        if seen.contains(&keys[0]) {
+            return syn::Error::new(binding.gadget.span(), format!("gadget `{}` is listed twice", keys[0]))
+                .to_compile_error()
+                .into();
+        }
+        seen.push(keys[0]);
+
+        let path: syn::Path = syn::parse_str(&format!("::zk_frontend::{path}")).unwrap();
+        let assoc = Ident::new(assoc, binding.gadget.span());
+        let instance = &binding.instance;
+        impls.push(quote! {
+            impl #impl_generics #path for #circuit #where_clause {
+                type #assoc = #instance;
+            }
+        });
+    }
+
+    quote!(#(#impls)*).into()
+}
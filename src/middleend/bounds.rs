@@ -0,0 +1,281 @@
+use std::{collections::{BTreeSet, HashMap}, hash::Hash};
+
+use ff::{Field, PrimeField};
+use num_bigint::BigUint;
+
+use crate::circuit::{ConstraintLog, Lookups, RangeBound, Table};
+
+/// Result of bound propagation. Bounds are exclusive, as in RangeBound.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoundsReport<A> {
+    /// Range checks (index of the lookup, checked signal) whose bound is already implied by other constraints.
+    pub redundant: Vec<(usize, A)>,
+    /// Combinations (index of the constraint, defined signal) which may exceed the field modulus, so the defined
+    /// signal is not bounded by them.
+    pub wraps: Vec<(usize, A)>,
+    /// Signals whose RangeBound was set or tightened, by bounds from RangeBound only.
+    pub tightened: usize,
+    /// Bounds which rely on range checks, tighter than the ones implied by RangeBound alone. They are not written
+    /// to RangeBound, as removal of a range check could then be justified by the check itself.
+    pub checked: Vec<(A, BigUint)>,
+}
+
+/// Converts field element to integer. Assumes little-endian representation, as advise_limbs does.
+fn to_biguint<F: PrimeField>(x: &F) -> BigUint {
+    BigUint::from_bytes_le(x.to_repr().as_ref())
+}
+
+/// Returns n if the table consists of values 0..n in a single column, i.e. it is a range check table.
+pub(super) fn range_size<F: PrimeField>(table: &Table<F>) -> Option<usize> {
+    let mut value = F::ZERO;
+    for row in table.rows.iter() {
+        if table.columns != 1 || row[0] != value {
+            return None;
+        }
+        value += F::ONE;
+    }
+    Some(table.rows.len())
+}
+
+/// Constraint defining signal t as a combination sum_i coeff_i * prod_j sig_ij of other signals with non-negative
+/// (i.e. smaller than half of the modulus) integer coefficients. A term without signals is a constant term.
+struct Definition<A> {
+    constraint: usize,
+    t: A,
+    terms: Vec<(BigUint, Vec<A>)>,
+}
+
+/// Finds all ways to read constraints as definitions: for every signal occurring once and linearly, tries to
+/// express it through the others.
+fn definitions<C: ConstraintLog>(c: &C) -> Vec<Definition<C::RawAddr>> {
+    let half = to_biguint(&-C::F::ONE) >> 1;
+    let mut definitions = vec![];
+    for (i, constraint) in c.constraints().iter().enumerate() {
+        for (coeff, sigs) in constraint.terms.iter() {
+            let [t] = sigs[..] else { continue };
+            if constraint.terms.iter().filter(|(_, sigs)| sigs.contains(&t)).count() != 1 {
+                continue;
+            }
+            let factor = -coeff.invert().unwrap();
+            let terms: Vec<_> = constraint.terms.iter()
+                .filter(|(_, sigs)| !sigs.contains(&t))
+                .map(|(other, sigs)| (to_biguint(&(*other * factor)), sigs.clone()))
+                .collect();
+            if terms.iter().all(|(coeff, _)| coeff <= &half) {
+                definitions.push(Definition { constraint: i, t, terms });
+            }
+        }
+    }
+    definitions
+}
+
+/// Bound together with the range checks (indices of lookups) it relies on.
+#[derive(Clone)]
+struct Bound {
+    value: BigUint,
+    checks: BTreeSet<usize>,
+}
+
+/// Maximal value of a definition, given the bounds of its signals. None if some bound is unknown, unless it is
+/// multiplied by a signal with bound 0.
+fn max_value<A: Copy>(definition: &Definition<A>, bounds: &impl Fn(A) -> Option<Bound>) -> Option<Bound> {
+    let zero = BigUint::from(0u32);
+    let mut max = Bound { value: zero.clone(), checks: BTreeSet::new() };
+    for (coeff, sigs) in definition.terms.iter() {
+        let factors: Vec<Option<Bound>> = sigs.iter().map(|sig| bounds(*sig)).collect();
+        if let Some(factor) = factors.iter().flatten().find(|factor| factor.value == zero) {
+            max.checks.extend(factor.checks.iter().copied());
+            continue;
+        }
+        let mut term = coeff.clone();
+        for bound in factors {
+            let bound = bound?;
+            term *= bound.value - 1u32;
+            max.checks.extend(bound.checks);
+        }
+        max.value += term;
+    }
+    Some(max)
+}
+
+/// Smaller of the implied and the checked bound.
+fn total<A: Copy + Eq + Hash>(implied: &HashMap<A, Bound>, checked: &HashMap<A, Bound>, addr: A) -> Option<Bound> {
+    match (implied.get(&addr), checked.get(&addr)) {
+        (Some(a), Some(b)) => Some(if b.value < a.value { b } else { a }.clone()),
+        (a, b) => a.or(b).cloned(),
+    }
+}
+
+/// Tightens implied bounds by the definitions until a fixpoint, given the bounds known from range checks.
+fn propagate<A: Copy + Eq + Hash>(
+    definitions: &[Definition<A>],
+    mut implied: HashMap<A, Bound>,
+    checked: &HashMap<A, Bound>,
+    modulus: &BigUint,
+) -> HashMap<A, Bound> {
+    // Bounds only decrease, but cyclic definitions could decrease them slowly; each signal needs at most
+    // as many rounds as there are definitions if there are no cycles.
+    for _ in 0..=definitions.len() {
+        let mut changed = false;
+        for definition in definitions.iter() {
+            let Some(max) = max_value(definition, &|addr| total(&implied, checked, addr)) else { continue };
+            if &max.value >= modulus {
+                continue;
+            }
+            let bound = Bound { value: max.value + 1u32, checks: max.checks };
+            if implied.get(&definition.t).map_or(true, |old| bound.value < old.value) {
+                implied.insert(definition.t, bound);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    implied
+}
+
+/// Propagates range bounds through the constraints, like num_linear_combination and num_mul do locally.
+/// Bounds are known from RangeBound and from lookups into range check tables (see range_table), and propagate
+/// through every constraint which defines a signal as a non-negative combination of others. Bounds implied by
+/// RangeBound alone which are tighter than the ones in RangeBound are written back; bounds relying on range checks
+/// are only reported (see BoundsReport::checked).
+/// A range check is redundant if the bound of its signal is implied by the other range checks and RangeBound;
+/// bounds set by assume are trusted.
+pub fn propagate_bounds<C>(c: &mut C) -> BoundsReport<C::RawAddr>
+where
+    C: ConstraintLog + Lookups + RangeBound,
+{
+    let modulus = to_biguint(&-C::F::ONE) + 1u32;
+    let mut report = BoundsReport { redundant: vec![], wraps: vec![], tightened: 0, checked: vec![] };
+
+    let range_sizes: Vec<Option<usize>> = c.tables().iter().map(range_size).collect();
+    let checks: Vec<(usize, C::RawAddr, BigUint)> = c.lookups().iter().enumerate()
+        .filter_map(|(i, lookup)| match (range_sizes[lookup.table.0], &lookup.sigs[..]) {
+            (Some(size), [sig]) => Some((i, *sig, BigUint::from(size))),
+            _ => None,
+        })
+        .collect();
+    let mut checked: HashMap<C::RawAddr, Bound> = HashMap::new();
+    for (i, sig, size) in checks.iter() {
+        if checked.get(sig).map_or(true, |bound| size < &bound.value) {
+            checked.insert(*sig, Bound { value: size.clone(), checks: BTreeSet::from([*i]) });
+        }
+    }
+    let assumed: HashMap<C::RawAddr, Bound> = c.raw_addrs()
+        .into_iter()
+        .filter_map(|addr| c.bound(addr).map(|value| (addr, Bound { value, checks: BTreeSet::new() })))
+        .collect();
+
+    let definitions = definitions(c);
+    let trusted = propagate(&definitions, assumed.clone(), &HashMap::new(), &modulus);
+    let implied = propagate(&definitions, assumed, &checked, &modulus);
+
+    for definition in definitions.iter() {
+        if let Some(max) = max_value(definition, &|addr| total(&implied, &checked, addr)) {
+            if max.value >= modulus {
+                report.wraps.push((definition.constraint, definition.t));
+            }
+        }
+    }
+
+    // Implied bounds are only propagated once, so a check is redundant if another check on the same signal is at
+    // least as tight, or if the tightest implied bound is tight enough and does not rely on the check itself. This
+    // misses looser implied bounds which would suffice without the check.
+    for (i, sig, size) in checks.iter() {
+        let duplicate = checks.iter().any(|(j, other, other_size)| j != i && other == sig && other_size <= size);
+        let implied = implied.get(sig).is_some_and(|bound| &bound.value <= size && !bound.checks.contains(i));
+        if duplicate || implied {
+            report.redundant.push((*i, *sig));
+        }
+    }
+
+    for addr in c.raw_addrs() {
+        let Some(bound) = implied.get(&addr) else { continue };
+        if trusted.get(&addr).map_or(true, |old| bound.value < old.value) {
+            report.checked.push((addr, bound.value.clone()));
+        }
+    }
+    for (addr, bound) in trusted {
+        if c.bound(addr).map_or(true, |old| bound.value < old) {
+            c._set_bound(addr, Some(&bound.value));
+            report.tightened += 1;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use halo2curves::bn256::Fr;
+    use num_bigint::BigUint;
+
+    use crate::{
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, Lookups, RangeBound, ToRawAddr, _Into},
+        gadgets::traits::bigint_arith::range_table,
+        test_utils::TestCircuit,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_propagate_bounds() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let limbs = [c.alloc_input::<Fr>(), c.alloc_input::<Fr>()];
+        let small = range_table(&mut c, 16);
+        let large = range_table(&mut c, 256);
+        c.lookup(small, &[limbs[0]]);
+        c.lookup(small, &[limbs[1]]);
+        let x = c.advise_sigs(&limbs, 1, |x| vec![x[0] + Fr::from(16) * x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into()]).term(-Fr::one(), &[limbs[0]._into()]).term(-Fr::from(16), &[limbs[1]._into()]));
+        c.lookup(large, &[x]);
+        let y = c.advise_sigs(&[x], 1, |x| vec![x[0] * Fr::from(2).pow_vartime([250])])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[y._into()]).term(-Fr::from(2).pow_vartime([250]), &[x._into()]));
+
+        let report = propagate_bounds(&mut c);
+        assert_eq!(report.redundant, vec![(2, x.to_raw_addr())]);
+        assert_eq!(report.wraps, vec![(1, y.to_raw_addr())]);
+        assert_eq!(report.checked, vec![(x.to_raw_addr(), BigUint::from(256u32))]);
+        assert_eq!(report.tightened, 0);
+        assert_eq!(c.bound(x.to_raw_addr()), None);
+        assert_eq!(c.bound(limbs[0].to_raw_addr()), None);
+        assert_eq!(propagate_bounds(&mut c), report);
+    }
+
+    #[test]
+    fn test_propagate_bounds_own_check() {
+        // x = y and y = x: the only range check on x must not justify itself through y.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let x = c.alloc_input::<Fr>();
+        let y = c.alloc_input::<Fr>();
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into()]).term(-Fr::one(), &[y._into()]));
+        let range = range_table(&mut c, 16);
+        c.lookup(range, &[x]);
+
+        let report = propagate_bounds(&mut c);
+        assert!(report.redundant.is_empty());
+        let bound = BigUint::from(16u32);
+        assert_eq!(report.checked, vec![(x.to_raw_addr(), bound.clone()), (y.to_raw_addr(), bound)]);
+
+        // A duplicated check is redundant, as the other one remains.
+        c.lookup(range, &[x]);
+        assert_eq!(propagate_bounds(&mut c).redundant, vec![(0, x.to_raw_addr()), (1, x.to_raw_addr())]);
+    }
+
+    #[test]
+    fn test_propagate_bounds_assumed() {
+        // t = z * w + a, where z has bound 0 and w is unbounded: only a contributes.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let [z, w, a, t] = [(); 4].map(|_| c.alloc_input::<Fr>());
+        c._set_bound(z.to_raw_addr(), Some(&BigUint::from(0u32)));
+        c._set_bound(a.to_raw_addr(), Some(&BigUint::from(16u32)));
+        c.constrain(Constraint::new().term(Fr::one(), &[t._into()]).term(-Fr::one(), &[z._into(), w._into()]).term(-Fr::one(), &[a._into()]));
+
+        let report = propagate_bounds(&mut c);
+        assert_eq!(report.tightened, 1);
+        assert!(report.checked.is_empty());
+        assert_eq!(c.bound(t.to_raw_addr()), Some(BigUint::from(16u32)));
+    }
+}
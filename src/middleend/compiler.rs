@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    backend::{api::{RTGraph, RTNode}, storage::{RawAllocator, Storage}},
+    circuit::{
+        AdviceLog, AliasFlag, ChallengeFlag, Circuit, CommitmentGroups, ConstantFlag, ConstraintLog, InputFlag, Lookups,
+        PublicFlag, SignalFlag,
+    },
+};
+
+/// Mapping of circuit addresses to storage addresses.
+pub type AddrMapping<C, S> = HashMap<<C as Circuit>::RawAddr, <S as Storage>::RawAddr>;
+
+/// Allocates storage for every variable of the circuit, in order of allocation. Merged values (see AliasFlag) share
+/// storage with the value they were merged into.
+pub fn allocate<C, S>(c: &C, s: &mut S) -> AddrMapping<C, S>
+where
+    C: Circuit + SignalFlag + AliasFlag,
+    S: RawAllocator,
+{
+    let mut mapping: AddrMapping<C, S> = c.raw_addrs()
+        .into_iter()
+        .filter(|addr| c.is_var(*addr))
+        .map(|addr| (addr, s.allocate_raw(c.inner_type(addr))))
+        .collect();
+    for addr in c.raw_addrs() {
+        if c.alias(addr).is_some() {
+            let slot = mapping[&c.resolve(addr)];
+            mapping.insert(addr, slot);
+        }
+    }
+    mapping
+}
+
+/// Compiles recorded advices of the circuit into an execution graph over the given mapping.
+/// Outputs of the graph are the signals read by the backend: committed, public, constrained or looked up ones.
+/// Constants which no advice writes are provided externally, see RTGraph::constants.
+/// Panics if the circuit was not built in BuildMode::Full, or if the mapping misses some variable.
+pub fn compile_with<C, S>(c: &C, s: &mut S, mapping: &AddrMapping<C, S>) -> RTGraph<S>
+where
+    C: AdviceLog<Storage = S> + ConstraintLog + Lookups + SignalFlag + ChallengeFlag + CommitmentGroups + InputFlag
+        + PublicFlag + ConstantFlag,
+    S: Storage,
+{
+    let map = |addr: C::RawAddr| *mapping.get(&addr).expect("address is not allocated in storage");
+
+    let mut advices = vec![];
+    for record in c.advice_log().iter() {
+        let advice = record.advice.as_ref().expect("only circuits built in BuildMode::Full can be compiled");
+        advices.push(RTNode { advice: advice.compile(s, &map), namespace: record.namespace.clone() });
+    }
+
+    let constrained: HashSet<C::RawAddr> = c.constraints().iter()
+        .flat_map(|constraint| constraint.terms.iter().flat_map(|(_, sigs)| sigs.iter().copied()))
+        .chain(c.lookups().iter().flat_map(|lookup| lookup.sigs.iter().copied()))
+        .collect();
+    let written: HashSet<C::RawAddr> = c.advice_log().iter().flat_map(|record| record.outputs.iter().copied()).collect();
+    let mut inputs = vec![];
+    let mut constants = vec![];
+    let mut outputs = vec![];
+    let mut groups: Vec<Vec<S::RawAddr>> = vec![];
+    let mut challenges = vec![];
+    for addr in c.raw_addrs() {
+        if let Some(after) = c.challenge_after(addr) {
+            challenges.push((after, map(addr)));
+            continue;
+        }
+        if c.is_var(addr) && c.is_input(addr) {
+            inputs.push(map(addr));
+        }
+        if c.is_var(addr) && c.is_const(addr) && !written.contains(&addr) {
+            constants.push(map(addr));
+        }
+        let read = c.group(addr).is_some() || c.is_public(addr) || constrained.contains(&addr);
+        if c.is_var(addr) && c.is_sig(addr) && read {
+            outputs.push(map(addr));
+        }
+        if let Some(group) = c.group(addr) {
+            if groups.len() <= group {
+                groups.resize_with(group + 1, Vec::new);
+            }
+            groups[group].push(map(addr));
+        }
+    }
+
+    RTGraph::new(inputs, constants, outputs, advices, groups, challenges)
+}
+
+/// Allocates storage for the circuit and compiles it into an execution graph.
+pub fn compile<C, S>(c: &C, s: &mut S) -> (RTGraph<S>, AddrMapping<C, S>)
+where
+    C: AdviceLog<Storage = S> + ConstraintLog + Lookups + SignalFlag + ChallengeFlag + CommitmentGroups + InputFlag
+        + PublicFlag + ConstantFlag + AliasFlag,
+    S: RawAllocator,
+{
+    let mapping = allocate(c, s);
+    let graph = compile_with(c, s, &mapping);
+    (graph, mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Inputs, Publics, ToRawAddr},
+        test_utils::{execute, square, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| vec![x[0] * x[0]])[0];
+        let d = c.advise_sigs(&[a, b], 2, |x| vec![x[0] + x[1], x[1].double()]);
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        assert_eq!(graph.inputs(), &vec![mapping[&a.to_raw_addr()]]);
+        assert_eq!(graph.outputs().len(), 4);
+        assert_eq!(graph.groups()[0].len(), 4);
+
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&b.to_raw_addr()]), &Fr::from(9));
+        assert_eq!(s.get(&mapping[&d[0].to_raw_addr()]), &Fr::from(12));
+        assert_eq!(s.get(&mapping[&d[1].to_raw_addr()]), &Fr::from(18));
+    }
+
+    #[test]
+    fn test_compile_outputs() {
+        // Dependent signals are only outputs if the backend reads them.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = square(&mut c, a);
+        let [t, p] = [(); 2].map(|_| c.advise_dependent(&[b], 1, |x| x)[0]);
+        c.make_public(p);
+
+        let (graph, mapping) = compile(&c, &mut TestStorage::default());
+        let outputs = [a, b, p].map(|sig| mapping[&sig.to_raw_addr()]);
+        assert_eq!(graph.outputs(), &outputs.to_vec());
+        assert!(!graph.outputs().contains(&mapping[&t.to_raw_addr()]));
+    }
+
+    #[test]
+    #[should_panic(expected = "only circuits built in BuildMode::Full can be compiled")]
+    fn test_compile_shape() {
+        let mut c = TestCircuit::new(BuildMode::Shape);
+        let a = c.alloc_input::<Fr>();
+        c.advise_sigs(&[a], 1, |x| x);
+        compile(&c, &mut TestStorage::default());
+    }
+}
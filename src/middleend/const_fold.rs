@@ -0,0 +1,252 @@
+use std::{any::TypeId, collections::{HashMap, HashSet}, fmt::{self, Display}};
+
+use crate::{
+    backend::storage::{RawAllocator, ReaderOf},
+    circuit::{AdviceLog, Circuit, ConstantFlag, Constraint, ConstraintLog, Lookups, PrimarySignalFlag},
+};
+
+use super::compiler::AddrMapping;
+
+/// What constant folding has done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConstantFoldingReport {
+    /// Advices evaluated at compile time.
+    pub advices: usize,
+    /// Values which became constants.
+    pub constants: usize,
+    /// Constraints which had some constant signals substituted.
+    pub simplified: usize,
+    /// Constraints which became trivially satisfied and were removed.
+    pub removed: usize,
+    /// Lookups of constant signals only, checked at compile time and removed.
+    pub lookups: usize,
+}
+
+/// Constraint or lookup which can not be satisfied once constants are substituted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstantFoldingError {
+    /// Index of the constraint in the constraint log.
+    Constraint(usize),
+    /// Index of the lookup, and name of the table which has no such row.
+    Lookup(usize, String),
+}
+
+impl Display for ConstantFoldingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstantFoldingError::Constraint(i) => write!(f, "constraint {i} is unsatisfiable"),
+            ConstantFoldingError::Lookup(i, table) => write!(f, "lookup {i} into table {table} is unsatisfiable"),
+        }
+    }
+}
+
+/// Evaluates advices all inputs of which are constants computed by advices evaluated before, and sets the constant
+/// flag of their outputs. Advices without inputs are only evaluated if all their outputs are already flagged
+/// constant, as they may capture witness values otherwise. Advices writing committed signals are never evaluated.
+/// Values are computed in the scratch storage s. The advices themselves are kept, so the values are also written at
+/// runtime.
+/// Then substitutes values of constant signals of type C::F into constraints, and checks lookups all signals of which
+/// are constant. Signals which are also looked up by remaining lookups are not substituted, so they stay constrained.
+/// Returns an error if some constraint or lookup becomes unsatisfiable, leaving constraints and lookups unchanged.
+pub fn fold_constants<C>(c: &mut C, s: &mut C::Storage) -> Result<ConstantFoldingReport, ConstantFoldingError>
+where
+    C: AdviceLog + ConstantFlag + ConstraintLog + Lookups + PrimarySignalFlag,
+    C::Storage: RawAllocator + ReaderOf<C::F>,
+{
+    let mut report = ConstantFoldingReport::default();
+    let mut mapping: AddrMapping<C, C::Storage> = HashMap::new();
+    let mut known = HashSet::new();
+
+    for record in c.advice_log().iter() {
+        let Some(advice) = record.advice.as_ref() else { continue };
+        let foldable = if record.inputs.is_empty() {
+            !record.outputs.is_empty() && record.outputs.iter().all(|addr| c.is_const(*addr))
+        } else {
+            record.inputs.iter().all(|addr| known.contains(addr))
+        };
+        if !foldable || record.outputs.iter().any(|addr| c.is_sig(*addr) && c.is_primary(*addr)) {
+            continue;
+        }
+        for addr in record.outputs.iter() {
+            mapping.entry(*addr).or_insert_with(|| s.allocate_raw(c.inner_type(*addr)));
+        }
+        advice.compile(s, &|addr| mapping[&addr]).call(s);
+        known.extend(record.outputs.iter().copied());
+        report.advices += 1;
+    }
+
+    for addr in known.iter() {
+        if !c.is_const(*addr) {
+            c._set_const_flag(*addr, true);
+            report.constants += 1;
+        }
+    }
+
+    let mut values: HashMap<C::RawAddr, C::F> = known.iter()
+        .filter(|addr| c.is_sig(**addr) && c.inner_type(**addr) == TypeId::of::<C::F>())
+        .map(|addr| (*addr, *s.get(&mapping[addr])))
+        .collect();
+    if values.is_empty() {
+        return Ok(report);
+    }
+
+    let mut lookups = vec![];
+    for (i, lookup) in c.lookups().iter().enumerate() {
+        let row: Option<Vec<C::F>> = lookup.sigs.iter().map(|sig| values.get(sig).copied()).collect();
+        match row {
+            Some(row) => {
+                let table = &c.tables()[lookup.table.0];
+                if !table.rows.contains(&row) {
+                    return Err(ConstantFoldingError::Lookup(i, table.name.clone()));
+                }
+                report.lookups += 1;
+            }
+            None => lookups.push(lookup.clone()),
+        }
+    }
+    for lookup in lookups.iter() {
+        for sig in lookup.sigs.iter() {
+            values.remove(sig);
+        }
+    }
+
+    let mut constraints = vec![];
+    for (i, constraint) in c.constraints().iter().enumerate() {
+        match substitute(constraint, &values) {
+            None => constraints.push(constraint.clone()),
+            Some(constraint) => {
+                report.simplified += 1;
+                if constraint.is_trivial() {
+                    report.removed += 1;
+                    continue;
+                }
+                if constraint.is_unsatisfiable() {
+                    return Err(ConstantFoldingError::Constraint(i));
+                }
+                constraints.push(constraint);
+            }
+        }
+    }
+
+    *c.constraints_mut() = constraints;
+    *c.lookups_mut() = lookups;
+    Ok(report)
+}
+
+/// Substitutes known values into the constraint, merging terms with identical signals and dropping zero terms.
+/// Returns None if the constraint has no signals with known values.
+fn substitute<C: Circuit>(constraint: &Constraint<C>, values: &HashMap<C::RawAddr, C::F>) -> Option<Constraint<C>> {
+    if !constraint.terms.iter().any(|(_, sigs)| sigs.iter().any(|sig| values.contains_key(sig))) {
+        return None;
+    }
+
+    let mut terms = vec![];
+    for (coeff, sigs) in constraint.terms.iter() {
+        let mut coeff = *coeff;
+        let mut rest = vec![];
+        for sig in sigs.iter() {
+            match values.get(sig) {
+                Some(value) => coeff *= value,
+                None => rest.push(*sig),
+            }
+        }
+        terms.push((coeff, rest));
+    }
+
+    Some(Constraint { terms, namespace: constraint.namespace.clone() }.simplify())
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, ConstantFlag, Constraint, ConstraintLog, Inputs, Lookups, ToRawAddr, _Into},
+        test_utils::{TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    /// Constant signal of value 2, written by an advice without inputs.
+    fn two(c: &mut TestCircuit) -> TSig {
+        let k = c.advise_dependent(&[], 1, |_| vec![Fr::from(2)])[0];
+        c._set_const_flag(k.to_raw_addr(), true);
+        k
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let k = two(&mut c);
+        let m = c.advise_dependent(&[k], 1, |x| vec![x[0] * x[0]])[0];
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a, k], 1, |x| vec![x[0] * x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[m._into()]).term(-Fr::from(4), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[k._into(), a._into()]).term(-Fr::one(), &[b._into()]));
+
+        let report = fold_constants(&mut c, &mut TestStorage::default()).unwrap();
+        assert_eq!(report, ConstantFoldingReport { advices: 2, constants: 1, simplified: 2, removed: 1, lookups: 0 });
+        assert!(c.is_const(m.to_raw_addr()));
+        assert!(!c.is_const(b.to_raw_addr()));
+        assert_eq!(c.constraints().len(), 1);
+        assert_eq!(c.constraints()[0].terms, vec![
+            (Fr::from(2), vec![a.to_raw_addr()]),
+            (-Fr::one(), vec![b.to_raw_addr()]),
+        ]);
+    }
+
+    #[test]
+    fn test_fold_constants_keeps_witnesses() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let k = two(&mut c);
+        // Captured witness, and a committed signal computed from a constant.
+        let w = c.advise_dependent(&[], 1, |_| vec![Fr::from(5)])[0];
+        let p = c.advise_sigs(&[k], 1, |x| x)[0];
+        let q = c.advise_dependent(&[p], 1, |x| x)[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[w._into()]).term(-Fr::one(), &[p._into()]).term(-Fr::one(), &[q._into()]));
+
+        let report = fold_constants(&mut c, &mut TestStorage::default()).unwrap();
+        assert_eq!(report, ConstantFoldingReport { advices: 1, ..Default::default() });
+        assert!([w, p, q].iter().all(|sig| !c.is_const(sig.to_raw_addr())));
+        assert_eq!(c.constraints()[0].terms.len(), 3);
+    }
+
+    #[test]
+    fn test_fold_constants_lookups() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let table = c.declare_table("small", 1, vec![vec![Fr::from(2)], vec![Fr::from(4)]]);
+        let pairs = c.declare_table("pairs", 2, vec![vec![Fr::from(4), Fr::from(1)]]);
+        let k = two(&mut c);
+        let m = c.advise_dependent(&[k], 1, |x| vec![x[0] * x[0]])[0];
+        let a = c.alloc_input::<Fr>();
+        c.constrain(Constraint::new().term(Fr::one(), &[m._into()]).term(-Fr::from(4), &[]));
+        c.lookup(table, &[k]);
+        c.lookup(pairs, &[m, a]);
+
+        // m is still looked up together with a, so its defining constraint is kept.
+        let report = fold_constants(&mut c, &mut TestStorage::default()).unwrap();
+        assert_eq!(report, ConstantFoldingReport { advices: 2, constants: 1, simplified: 0, removed: 0, lookups: 1 });
+        assert_eq!(c.lookups().len(), 1);
+        assert_eq!(c.lookups()[0].sigs, vec![m.to_raw_addr(), a.to_raw_addr()]);
+        assert_eq!(c.constraints().len(), 1);
+    }
+
+    #[test]
+    fn test_fold_constants_unsatisfiable() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let k = two(&mut c);
+        c.constrain(Constraint::new().term(Fr::one(), &[k._into()]).term(-Fr::from(2), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[k._into()]).term(-Fr::from(3), &[]));
+        assert_eq!(fold_constants(&mut c, &mut TestStorage::default()), Err(ConstantFoldingError::Constraint(1)));
+        assert_eq!(c.constraints().len(), 2);
+
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let table = c.declare_table("odd", 1, vec![vec![Fr::from(1)], vec![Fr::from(3)]]);
+        let k = two(&mut c);
+        c.lookup(table, &[k]);
+        let error = fold_constants(&mut c, &mut TestStorage::default()).unwrap_err();
+        assert_eq!(error, ConstantFoldingError::Lookup(0, "odd".into()));
+        assert_eq!(error.to_string(), "lookup 0 into table odd is unsatisfiable");
+        assert_eq!(c.lookups().len(), 1);
+    }
+}
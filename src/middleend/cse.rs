@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use ff::PrimeField;
+use itertools::Itertools;
+
+use crate::circuit::{
+    AdviceLog, AliasFlag, CommitmentGroups, Constraint, ConstraintLog, FuncId, Lookup, Lookups, PrimarySignalFlag,
+    PublicFlag,
+};
+
+/// What common subexpression elimination has done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CseReport {
+    /// Removed duplicate advices.
+    pub advices: usize,
+    /// Values merged into outputs of the kept advices.
+    pub merged: usize,
+    /// Constraints removed as duplicates (or trivial) after merging.
+    pub constraints: usize,
+    /// Lookups removed as duplicates after merging.
+    pub lookups: usize,
+}
+
+/// Finds advices of the same function (see AdviceRecord::func_id) over the same inputs, keeps the first one and merges
+/// outputs of the others into its outputs (see AliasFlag). Inputs are compared after merging, so chains of identical
+/// gadget calls collapse entirely. Then rewrites constraints and lookups over merged values and removes duplicates.
+pub fn eliminate_common_subexpressions<C>(c: &mut C) -> CseReport
+where
+    C: AdviceLog + ConstraintLog + Lookups + AliasFlag + PrimarySignalFlag + CommitmentGroups + PublicFlag,
+{
+    let mut report = CseReport::default();
+
+    // Advices are only compared with equal amounts of outputs, so that merging never misaligns them.
+    let mut seen: HashMap<(FuncId, Vec<C::RawAddr>, usize), usize> = HashMap::new();
+    let records = std::mem::take(c.advice_log_mut());
+    for mut record in records {
+        record.inputs = record.inputs.iter().map(|addr| c.resolve(*addr)).collect();
+        let Some(func_id) = record.func_id.clone() else {
+            c.advice_log_mut().push(record);
+            continue;
+        };
+        let key = (func_id, record.inputs.clone(), record.outputs.len());
+        match seen.get(&key) {
+            None => {
+                seen.insert(key, c.advice_log().len());
+                c.advice_log_mut().push(record);
+            }
+            Some(&kept) => {
+                let reps = c.advice_log()[kept].outputs.clone();
+                for (addr, rep) in record.outputs.iter().zip_eq(reps) {
+                    merge(c, *addr, rep);
+                    report.merged += 1;
+                }
+                report.advices += 1;
+            }
+        }
+    }
+
+    // Normalised constraints are compared by the byte representations of their coefficients, as fields are not Hash.
+    let mut seen_constraints = HashSet::new();
+    let constraints = std::mem::take(c.constraints_mut());
+    for constraint in constraints {
+        let terms = constraint.terms.into_iter()
+            .map(|(coeff, sigs)| (coeff, sigs.into_iter().map(|sig| c.resolve(sig)).collect()))
+            .collect();
+        let constraint: Constraint<C> = Constraint { terms, namespace: constraint.namespace }.simplify();
+        let key: Vec<(Vec<u8>, Vec<C::RawAddr>)> = constraint.terms.iter()
+            .map(|(coeff, sigs)| (coeff.to_repr().as_ref().to_vec(), sigs.clone()))
+            .collect();
+        if constraint.is_trivial() || !seen_constraints.insert(key) {
+            report.constraints += 1;
+            continue;
+        }
+        c.constraints_mut().push(constraint);
+    }
+
+    let mut seen_lookups = HashSet::new();
+    let lookups = std::mem::take(c.lookups_mut());
+    for lookup in lookups {
+        let lookup = Lookup {
+            table: lookup.table,
+            sigs: lookup.sigs.into_iter().map(|sig| c.resolve(sig)).collect(),
+            namespace: lookup.namespace,
+        };
+        if !seen_lookups.insert((lookup.table, lookup.sigs.clone())) {
+            report.lookups += 1;
+            continue;
+        }
+        c.lookups_mut().push(lookup);
+    }
+
+    report
+}
+
+/// Merges addr into rep: rep takes over its commitment and public flag, and addr stops being a variable.
+fn merge<C>(c: &mut C, addr: C::RawAddr, rep: C::RawAddr)
+where
+    C: AliasFlag + PrimarySignalFlag + CommitmentGroups + PublicFlag,
+{
+    if c.is_primary(addr) && !c.is_primary(rep) {
+        c._set_primary_flag(rep, true);
+        c._set_group(rep, c.group(addr));
+    }
+    if c.is_public(addr) {
+        c._set_public_flag(rep, true);
+    }
+    c._set_public_flag(addr, false);
+    c._set_primary_flag(addr, false);
+    c._set_group(addr, None);
+    c._set_sig_flag(addr, false);
+    c._set_var_flag(addr, false);
+    c._set_alias(addr, Some(rep));
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::{
+            registry::{KindAdvices, Lc as RegistryLc, Pow5},
+            storage::{ReaderOf, WriterOf},
+        },
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, ToRawAddr, _Into},
+        gadgets::traits::atoms::LinearCombination,
+        middleend::{compiler::compile, validate::validate},
+        test_utils::{execute, square, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_eliminate_common_subexpressions() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b1 = square(&mut c, a);
+        let b2 = square(&mut c, a);
+        let d = c.advise_sigs(&[b1, b2], 1, |x| vec![x[0] + x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[d._into()]).term(-Fr::one(), &[b1._into()]).term(-Fr::one(), &[b2._into()]));
+
+        let report = eliminate_common_subexpressions(&mut c);
+        assert_eq!(report, CseReport { advices: 1, merged: 1, constraints: 1, lookups: 0 });
+        assert_eq!(c.constraints()[1].terms, vec![
+            (Fr::one(), vec![d.to_raw_addr()]),
+            (-Fr::from(2), vec![b1.to_raw_addr()]),
+        ]);
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        assert!(validate(&graph).is_empty());
+        assert_eq!(mapping[&b1.to_raw_addr()], mapping[&b2.to_raw_addr()]);
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&d.to_raw_addr()]), &Fr::from(18));
+    }
+
+    #[test]
+    fn test_cse_captured_params() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.alloc_input::<Fr>();
+        let lc1 = c.lc(vec![Fr::from(2), Fr::from(3)], vec![a, b]);
+        let lc2 = c.lc(vec![Fr::from(2), Fr::from(3)], vec![a, b]);
+        let lc3 = c.lc(vec![Fr::from(2), Fr::from(4)], vec![a, b]);
+        let k1 = c.advise_kind(Pow5, &[a], 1)[0];
+        let k2 = c.advise_kind(Pow5, &[a], 1)[0];
+        let l1 = c.advise_kind(RegistryLc { coeffs: vec![Fr::from(2)] }, &[a], 1)[0];
+        let l2 = c.advise_kind(RegistryLc { coeffs: vec![Fr::from(3)] }, &[a], 1)[0];
+        // Closures capturing values without describing them are never merged.
+        let k = Fr::from(5);
+        let m1 = c.advise_sigs(&[a], 1, move |x| vec![x[0] * k])[0];
+        let m2 = c.advise_sigs(&[a], 1, move |x| vec![x[0] * k])[0];
+
+        let report = eliminate_common_subexpressions(&mut c);
+        assert_eq!((report.advices, report.merged), (2, 2));
+        assert_eq!(c.resolve(lc2.to_raw_addr()), lc1.to_raw_addr());
+        assert_eq!(c.resolve(k2.to_raw_addr()), k1.to_raw_addr());
+        for (x, y) in [(lc3, lc1), (l2, l1), (m2, m1)] {
+            assert_ne!(c.resolve(x.to_raw_addr()), c.resolve(y.to_raw_addr()));
+        }
+    }
+
+    #[test]
+    fn test_cse_arities() {
+        fn repeat(x: Vec<Fr>) -> Vec<Fr> {
+            vec![x[0], x[0]]
+        }
+
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        c.advise_sigs(&[a], 2, repeat);
+        c.advise_sigs(&[a], 1, repeat);
+        let report = eliminate_common_subexpressions(&mut c);
+        assert_eq!(report, CseReport::default());
+        assert_eq!(c.advice_log().len(), 2);
+    }
+}
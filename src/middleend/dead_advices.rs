@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::circuit::{AdviceLog, ChallengeFlag, CommitmentGroups, ConstraintLog, InputFlag, Lookups, PublicFlag};
+
+/// What dead advice elimination has removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeadAdviceReport {
+    pub advices: usize,
+    /// Variables which no longer need storage.
+    pub slots: usize,
+}
+
+/// Returns values which are needed regardless of advices reading them: constrained signals (including lookups),
+/// committed signals (declared witness columns) and public outputs. Inputs and challenges are provided externally, and
+/// are kept as well. With prune_committed, committed signals are only kept if they are needed otherwise.
+fn roots<C>(c: &C, prune_committed: bool) -> Vec<C::RawAddr>
+where
+    C: ConstraintLog + Lookups + CommitmentGroups + PublicFlag + InputFlag + ChallengeFlag,
+{
+    let constrained = c.constraints().iter().flat_map(|constraint| constraint.terms.iter().flat_map(|(_, sigs)| sigs.iter().copied()));
+    let looked_up = c.lookups().iter().flat_map(|lookup| lookup.sigs.iter().copied());
+    let flagged = c.raw_addrs().into_iter().filter(|addr| {
+        c.is_var(*addr)
+            && ((!prune_committed && c.group(*addr).is_some())
+                || c.is_public(*addr)
+                || c.is_input(*addr)
+                || c.challenge_after(*addr).is_some())
+    });
+    constrained.chain(looked_up).chain(flagged).collect()
+}
+
+/// Removes advices whose outputs can not reach any root (see roots), and unsets the variable flag of values which
+/// are neither roots nor touched by remaining advices, so they are not allocated in storage. Committed signals are
+/// only removed (also from their commitment groups) if prune_committed is set, i.e. the caller does not need
+/// unconstrained witness columns.
+pub fn eliminate_dead_advices<C>(c: &mut C, prune_committed: bool) -> DeadAdviceReport
+where
+    C: AdviceLog + ConstraintLog + Lookups + CommitmentGroups + PublicFlag + InputFlag + ChallengeFlag,
+{
+    let mut writers: HashMap<C::RawAddr, Vec<usize>> = HashMap::new();
+    for (i, record) in c.advice_log().iter().enumerate() {
+        for addr in record.outputs.iter() {
+            writers.entry(*addr).or_default().push(i);
+        }
+    }
+
+    let mut live = HashSet::new();
+    let mut queue = vec![];
+    for addr in roots(c, prune_committed) {
+        if live.insert(addr) {
+            queue.push(addr);
+        }
+    }
+    let mut live_advices = vec![false; c.advice_log().len()];
+    while let Some(addr) = queue.pop() {
+        for &i in writers.get(&addr).into_iter().flatten() {
+            if live_advices[i] {
+                continue;
+            }
+            live_advices[i] = true;
+            let record = &c.advice_log()[i];
+            for addr in record.inputs.iter().chain(record.outputs.iter()) {
+                if live.insert(*addr) {
+                    queue.push(*addr);
+                }
+            }
+        }
+    }
+
+    let mut live_advices = live_advices.into_iter();
+    let before = c.advice_log().len();
+    c.advice_log_mut().retain(|_| live_advices.next().unwrap());
+    let advices = before - c.advice_log().len();
+
+    let mut slots = 0;
+    for addr in c.raw_addrs() {
+        if c.is_var(addr) && !live.contains(&addr) {
+            c._set_sig_flag(addr, false);
+            c._set_var_flag(addr, false);
+            c._set_group(addr, None);
+            slots += 1;
+        }
+    }
+
+    DeadAdviceReport { advices, slots }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{AdviceLog, BuildMode, Constraint, ConstraintLog, Inputs, Publics, ToRawAddr, VariableFlag, _Into},
+        gadgets::traits::bigint_arith::advise_limbs,
+        middleend::{compiler::compile, validate::validate},
+        test_utils::{execute, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_eliminate_dead_advices() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let h = c.advise_dependent(&[a], 2, |x| vec![x[0], x[0].double()]);
+        c.constrain(Constraint::new().term(Fr::one(), &[h[0]._into()]));
+        c.advise_dependent(&[h[1]], 1, |x| x);
+        c.advise_dependent(&[a], 2, |x| vec![x[0], x[0]]);
+        let p = c.advise_dependent(&[h[1]], 1, |x| x)[0];
+        c.make_public(p);
+
+        assert_eq!(eliminate_dead_advices(&mut c, false), DeadAdviceReport { advices: 2, slots: 3 });
+        assert_eq!(c.advice_log().len(), 2);
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        assert!(validate(&graph).is_empty());
+        assert_eq!(mapping.len(), 4);
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&p.to_raw_addr()]), &Fr::from(6));
+    }
+
+    #[test]
+    fn test_eliminate_unused_limbs() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let limbs = advise_limbs(&mut c, a, &16u32.into(), 2);
+        let used = advise_limbs(&mut c, a, &256u32.into(), 2);
+        c.constrain(Constraint::new().term(Fr::one(), &[used[0]._into()]).term(Fr::from(256), &[used[1]._into()]).term(-Fr::one(), &[a._into()]));
+        assert!(limbs.iter().all(|limb| c.group(limb.to_raw_addr()).is_some()));
+
+        assert_eq!(eliminate_dead_advices(&mut c, true), DeadAdviceReport { advices: 1, slots: 2 });
+        assert_eq!(c.advice_log().len(), 1);
+        assert_eq!(c.advice_log()[0].outputs, used.iter().map(|limb| limb.to_raw_addr()).collect::<Vec<_>>());
+        assert!(limbs.iter().all(|limb| !c.is_var(limb.to_raw_addr()) && c.group(limb.to_raw_addr()).is_none()));
+    }
+
+    #[test]
+    fn test_keep_committed() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let committed = c.advise_sigs(&[a], 1, |x| vec![x[0].double()])[0];
+        c.advise_dependent(&[a], 1, |x| x);
+
+        assert_eq!(eliminate_dead_advices(&mut c, false), DeadAdviceReport { advices: 1, slots: 1 });
+        assert_eq!(c.advice_log()[0].outputs, vec![committed.to_raw_addr()]);
+        assert!(c.is_var(committed.to_raw_addr()) && c.group(committed.to_raw_addr()) == Some(0));
+    }
+}
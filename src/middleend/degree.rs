@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use ff::Field;
+
+use crate::{
+    backend::storage::{ReaderOf, WriterOf},
+    circuit::{AdviceLog, Constraint, ConstraintLog, HasSigtype, Sig, Signals, ToRawAddr, _Into},
+    gadgets::cost::CostEstimate,
+};
+
+/// Runtime parameters of degree reduction, carried by Circuit::Config.
+pub trait DegreeConfig {
+    /// Maximal degree of constraints accepted by the backend. At least 2.
+    fn max_degree(&self) -> usize;
+}
+
+/// What degree reduction has done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DegreeReductionReport {
+    /// Constraints which exceeded the degree bound.
+    pub rewritten: usize,
+    /// Intermediate signals, advices and constraints introduced.
+    pub cost: CostEstimate,
+}
+
+/// Rewrites constraints of degree above DegreeConfig::max_degree: while a term has too many factors, its first two
+/// factors are replaced by an intermediate signal t, which is computed by a new advice and constrained by t - a * b = 0.
+/// Intermediate products are shared between all constraints regardless of the order of a and b, so e.g. all terms x^5
+/// use the same x^2, x^3 and x^4. The reduction is greedy: factors are multiplied left to right, so terms sharing a
+/// product of factors which are not a prefix of both (e.g. x * y * z and w * y * z) do not share it.
+pub fn reduce_degree<C>(c: &mut C) -> DegreeReductionReport
+where
+    C: AdviceLog + ConstraintLog + Signals + 'static,
+    C::Config: DegreeConfig + HasSigtype<C::F>,
+    C::Storage: ReaderOf<C::F> + WriterOf<C::F> + 'static,
+{
+    let max_degree = c.config().max_degree();
+    assert!(max_degree >= 2, "degree bound must be at least 2");
+
+    let mut report = DegreeReductionReport::default();
+    let mut products: HashMap<(C::RawAddr, C::RawAddr), C::RawAddr> = HashMap::new();
+
+    let constraints = std::mem::take(c.constraints_mut());
+    for constraint in constraints {
+        if constraint.degree() <= max_degree {
+            c.constraints_mut().push(constraint);
+            continue;
+        }
+        report.rewritten += 1;
+        let mut terms = vec![];
+        for (coeff, mut sigs) in constraint.terms {
+            while sigs.len() > max_degree {
+                let (a, b) = (sigs[0].min(sigs[1]), sigs[0].max(sigs[1]));
+                let t = match products.get(&(a, b)) {
+                    Some(t) => *t,
+                    None => {
+                        let t = product(c, a, b, &constraint.namespace);
+                        report.cost += CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 };
+                        products.insert((a, b), t);
+                        t
+                    }
+                };
+                sigs.splice(0..2, [t]);
+            }
+            terms.push((coeff, sigs));
+        }
+        c.constraints_mut().push(Constraint { terms, namespace: constraint.namespace });
+    }
+
+    report
+}
+
+/// Allocates signal t, advises it to be a * b and constrains t - a * b = 0, recording both in the namespace.
+fn product<C>(c: &mut C, a: C::RawAddr, b: C::RawAddr, namespace: &str) -> C::RawAddr
+where
+    C: AdviceLog + ConstraintLog + Signals + 'static,
+    C::Config: HasSigtype<C::F>,
+    C::Storage: ReaderOf<C::F> + WriterOf<C::F> + 'static,
+{
+    let inputs: Vec<Sig<C, C::F>> = vec![c.sig_from_raw_addr(a), c.sig_from_raw_addr(b)];
+    let t: Sig<C, C::F> = c.alloc_sig();
+    let logged = c.advice_log().len();
+    c._log_advice(&inputs, &t, |x: Vec<C::F>| x[0] * x[1]);
+    for record in c.advice_log_mut()[logged..].iter_mut() {
+        record.namespace = namespace.to_string();
+    }
+    let mut constraint = Constraint::new().term(C::F::ONE, &[t._into()]).term(-C::F::ONE, &[inputs[0]._into(), inputs[1]._into()]);
+    constraint.namespace = namespace.to_string();
+    c.constraints_mut().push(constraint);
+    t.to_raw_addr()
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, ToRawAddr, _Into},
+        middleend::{compiler::compile, validate::validate},
+        test_utils::{check_constraints, execute, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_reduce_degree() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| vec![x[0] * x[0] * x[0] * x[0] * x[0]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::one(), &(0..5).map(|_| a._into()).collect::<Vec<_>>()));
+        let d = c.advise_sigs(&[a], 1, |x| vec![x[0] * x[0] * x[0]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[d._into()]).term(-Fr::one(), &(0..3).map(|_| a._into()).collect::<Vec<_>>()));
+
+        let report = reduce_degree(&mut c);
+        assert_eq!(report.rewritten, 2);
+        assert_eq!(report.cost.signals, 3);
+        assert!(c.constraints().iter().all(|constraint| constraint.degree() <= 2));
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        assert!(validate(&graph).is_empty());
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(2));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&b.to_raw_addr()]), &Fr::from(32));
+        check_constraints(&c, &s, &mapping);
+    }
+
+    #[test]
+    fn test_reduce_degree_shares_products() {
+        // x * y * z and y * x * w share x * y, and so does x * y * z * w.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let [x, y, z, w] = [(); 4].map(|_| c.alloc_input::<Fr>());
+        for factors in [[x, y, z].as_slice(), &[y, x, w], &[x, y, z, w]] {
+            let sigs: Vec<_> = factors.iter().map(|sig| sig._into()).collect();
+            c.constrain(Constraint::new().term(Fr::one(), &sigs).term(-Fr::one(), &[]));
+        }
+
+        let report = reduce_degree(&mut c);
+        assert_eq!(report.rewritten, 3);
+        assert_eq!(report.cost.signals, 2);
+        // Constraints of products go before the constraints using them.
+        let xy = c.constraints()[0].terms[0].1[0];
+        let xyz = c.constraints()[3].terms[0].1[0];
+        assert_eq!(c.constraints()[1].terms[0].1, vec![xy, z.to_raw_addr()]);
+        assert_eq!(c.constraints()[2].terms[0].1, vec![xy, w.to_raw_addr()]);
+        assert_eq!(c.constraints()[3].terms[1].1, vec![z.to_raw_addr(), xy]);
+        assert_eq!(c.constraints()[4].terms[0].1, vec![xyz, w.to_raw_addr()]);
+    }
+}
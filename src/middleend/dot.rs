@@ -0,0 +1,201 @@
+use std::{collections::{HashMap, HashSet}, fmt::{Debug, Write}, hash::Hash};
+
+use crate::{
+    backend::{api::RTGraph, storage::Storage},
+    circuit::{AdviceLog, AliasFlag, ChallengeFlag, ConstantFlag, SignalFlag},
+};
+
+use super::escape::escape;
+
+/// Kind of value passed between advices, determining the colour of its edges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Var,
+    Sig,
+    Const,
+    Challenge,
+}
+
+impl ValueKind {
+    fn color(self) -> &'static str {
+        match self {
+            ValueKind::Var => "gray",
+            ValueKind::Sig => "blue",
+            ValueKind::Const => "darkgreen",
+            ValueKind::Challenge => "red",
+        }
+    }
+}
+
+/// Part of the advice graph to export.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DotFilter<A> {
+    All,
+    /// Advices recorded in the namespace or in namespaces nested in it.
+    Namespace(String),
+    /// Advices the value depends on, i.e. its writer, writers of inputs of the writer, and so on.
+    Cone(A),
+}
+
+/// Advice as seen by the exporter: namespace, inputs and outputs.
+type Node<A> = (String, Vec<A>, Vec<A>);
+
+/// Exports advices of the circuit in DOT format. Advices are nodes labelled with their index in the advice log and
+/// namespace, values are edges from the advice writing them to advices reading them. Values read, but not written
+/// by any exported advice, are shown as separate nodes. Merged values (see AliasFlag) are shown as the value they
+/// were merged into.
+pub fn circuit_to_dot<C>(c: &C, filter: &DotFilter<C::RawAddr>) -> String
+where
+    C: AdviceLog + SignalFlag + ConstantFlag + ChallengeFlag + AliasFlag,
+{
+    let nodes: Vec<Node<C::RawAddr>> = c.advice_log().iter()
+        .map(|record| (
+            record.namespace.clone(),
+            record.inputs.iter().map(|addr| c.resolve(*addr)).collect(),
+            record.outputs.iter().map(|addr| c.resolve(*addr)).collect(),
+        ))
+        .collect();
+    let kind = |addr| if c.challenge_after(addr).is_some() {
+        ValueKind::Challenge
+    } else if c.is_const(addr) {
+        ValueKind::Const
+    } else if c.is_sig(addr) {
+        ValueKind::Sig
+    } else {
+        ValueKind::Var
+    };
+    render(&nodes, kind, filter)
+}
+
+/// Exports advices of the execution graph in DOT format, as circuit_to_dot does. Only constants provided externally
+/// (see RTGraph::constants) are distinguished, as the graph does not track constants computed by advices.
+pub fn graph_to_dot<S: Storage>(graph: &RTGraph<S>, filter: &DotFilter<S::RawAddr>) -> String {
+    let nodes: Vec<Node<S::RawAddr>> = graph.advices().iter()
+        .map(|node| (node.namespace.clone(), node.advice.inputs(), node.advice.outputs()))
+        .collect();
+    let challenges: HashSet<S::RawAddr> = graph.challenges().iter().map(|(_, addr)| *addr).collect();
+    let constants: HashSet<S::RawAddr> = graph.constants().iter().copied().collect();
+    let sigs: HashSet<S::RawAddr> = graph.outputs().iter().copied().collect();
+    let kind = |addr| if challenges.contains(&addr) {
+        ValueKind::Challenge
+    } else if constants.contains(&addr) {
+        ValueKind::Const
+    } else if sigs.contains(&addr) {
+        ValueKind::Sig
+    } else {
+        ValueKind::Var
+    };
+    render(&nodes, kind, filter)
+}
+
+fn render<A: Copy + Eq + Hash + Debug>(nodes: &[Node<A>], kind: impl Fn(A) -> ValueKind, filter: &DotFilter<A>) -> String {
+    let mut writers: HashMap<A, usize> = HashMap::new();
+    for (i, (_, _, outputs)) in nodes.iter().enumerate() {
+        for addr in outputs {
+            writers.entry(*addr).or_insert(i);
+        }
+    }
+
+    let shown: HashSet<usize> = match filter {
+        DotFilter::All => (0..nodes.len()).collect(),
+        DotFilter::Namespace(name) => (0..nodes.len())
+            .filter(|i| {
+                let namespace = &nodes[*i].0;
+                namespace == name || namespace.starts_with(&format!("{name}/"))
+            })
+            .collect(),
+        DotFilter::Cone(addr) => {
+            let mut shown = HashSet::new();
+            let mut stack: Vec<usize> = writers.get(addr).copied().into_iter().collect();
+            while let Some(i) = stack.pop() {
+                if shown.insert(i) {
+                    stack.extend(nodes[i].1.iter().filter_map(|addr| writers.get(addr).copied()));
+                }
+            }
+            shown
+        }
+    };
+
+    let mut out = String::from("digraph advices {\n    node [shape=box];\n");
+    let mut values: HashMap<A, usize> = HashMap::new();
+    for (i, (namespace, inputs, _)) in nodes.iter().enumerate() {
+        if !shown.contains(&i) {
+            continue;
+        }
+        writeln!(out, "    a{i} [label=\"#{i}\\n{}\"];", escape(namespace)).unwrap();
+        let mut seen = HashSet::new();
+        for addr in inputs.iter().filter(|addr| seen.insert(**addr)) {
+            let color = kind(*addr).color();
+            let label = escape(&format!("{addr:?}"));
+            match writers.get(addr) {
+                Some(writer) if shown.contains(writer) => {
+                    writeln!(out, "    a{writer} -> a{i} [label=\"{label}\", color={color}];").unwrap();
+                }
+                _ => {
+                    let next = values.len();
+                    let v = *values.entry(*addr).or_insert_with(|| {
+                        writeln!(out, "    v{next} [shape=ellipse, label=\"{label}\", color={color}];").unwrap();
+                        next
+                    });
+                    writeln!(out, "    v{v} -> a{i} [color={color}];").unwrap();
+                }
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, Challenges, Inputs, Namespaces, Signals, ToRawAddr},
+        middleend::compiler::compile,
+        test_utils::{square, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_dot() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let ch = c.alloc_challenge::<Fr>(0);
+        let ch: TSig = c.sig_from_raw_addr(ch.to_raw_addr());
+        let b = c.in_namespace("gadget", |c| c.in_namespace("square", |c| square(c, a)));
+        let d = c.advise_dependent(&[b, ch], 1, |x| vec![x[0] * x[1]])[0];
+        c.in_namespace("other", |c| c.advise_sigs(&[a], 1, |x| x));
+
+        let dot = circuit_to_dot(&c, &DotFilter::All);
+        assert!(dot.starts_with("digraph advices {\n"));
+        assert!(dot.contains("    a0 [label=\"#0\\ngadget/square\"];\n"));
+        assert!(dot.contains(&format!("    a0 -> a1 [label=\"{}\", color=blue];\n", b.to_raw_addr())));
+        assert!(dot.contains(&format!("    v1 [shape=ellipse, label=\"{}\", color=red];\n", ch.to_raw_addr())));
+        assert_eq!(dot.matches("v0 ->").count(), 2);
+
+        let dot = circuit_to_dot(&c, &DotFilter::Namespace("gadget".into()));
+        assert!(dot.contains("a0 ") && !dot.contains("a1 ") && !dot.contains("a2 "));
+
+        let (graph, mapping) = compile(&c, &mut TestStorage::default());
+        let dot = graph_to_dot(&graph, &DotFilter::Cone(mapping[&d.to_raw_addr()]));
+        assert!(dot.contains("a0 -> a1") && !dot.contains("a2 "));
+        assert!(dot.contains("color=red"));
+    }
+
+    #[test]
+    fn test_dot_constants_and_escapes() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let k = c.alloc_input::<Fr>();
+        c._set_const_flag(k.to_raw_addr(), true);
+        c.in_namespace("a\"b\tc\u{7}", |c| square(c, k));
+
+        let dot = circuit_to_dot(&c, &DotFilter::All);
+        assert!(dot.contains("    a0 [label=\"#0\\na\\\"b\\tc\\u0007\"];\n"));
+        assert!(dot.contains("color=darkgreen"));
+
+        let (graph, _) = compile(&c, &mut TestStorage::default());
+        assert!(graph_to_dot(&graph, &DotFilter::All).contains("color=darkgreen"));
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::Write;
+
+/// Escapes quotes, backslashes and control characters as JSON does, so the string fits in a quoted string of JSON
+/// or DOT, or on one line of a saved graph. Control characters other than line breaks and tabs become \uXXXX.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Inverse of escape. None if the string contains an unknown or incomplete escape sequence.
+pub(crate) fn unescape(s: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            '"' => out.push('"'),
+            '\\' => out.push('\\'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'u' => {
+                let code = chars.as_str().get(..4)?;
+                out.push(char::from_u32(u32::from_str_radix(code, 16).ok()?)?);
+                chars = chars.as_str()[4..].chars();
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        let s = " a\"b\\c\r\n\td\u{7}\u{7f}\u{e9} ";
+        assert_eq!(escape(s), " a\\\"b\\\\c\\r\\n\\td\\u0007\\u007f\u{e9} ");
+        assert_eq!(unescape(&escape(s)).as_deref(), Some(s));
+        assert_eq!(unescape("a\\u00"), None);
+        assert_eq!(unescape("a\\x"), None);
+        assert_eq!(unescape("a\\"), None);
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::{api::{KindCall, LocalValues, RTAdvice, RTGraph, RTNode}, storage::Storage};
+
+use super::rounds::round_bounds;
+
+/// Advice executing several advices in sequence, so the backend schedules (and waits for inputs of) a single node.
+/// Values passed between the parts which are not read outside of the fused advice are kept in it and never stored.
+pub struct FusedAdvice<S: Storage> {
+    parts: Vec<Box<dyn RTAdvice<S>>>,
+    kept: HashSet<S::RawAddr>,
+    inputs: Vec<S::RawAddr>,
+    outputs: Vec<S::RawAddr>,
+}
+
+impl<S: Storage> FusedAdvice<S> {
+    /// Parts are executed in the given order, so every part must go after the parts it reads from. Values written by
+    /// the parts are stored if they are exposed, i.e. read outside of the fused advice, or if some part using them
+    /// does not support local values (see RTAdvice::supports_local).
+    pub fn new(parts: Vec<Box<dyn RTAdvice<S>>>, exposed: &HashSet<S::RawAddr>) -> Self {
+        let internal: HashSet<S::RawAddr> = parts.iter().flat_map(|part| part.outputs()).collect();
+        let stored: HashSet<S::RawAddr> = parts.iter()
+            .filter(|part| !part.supports_local())
+            .flat_map(|part| part.inputs().into_iter().chain(part.outputs()))
+            .collect();
+        let kept: HashSet<S::RawAddr> = internal.iter()
+            .filter(|addr| !exposed.contains(addr) && !stored.contains(addr))
+            .copied()
+            .collect();
+        let outputs = parts.iter().flat_map(|part| part.outputs()).filter(|addr| !kept.contains(addr)).collect();
+        let mut seen = HashSet::new();
+        let inputs = parts.iter()
+            .flat_map(|part| part.inputs())
+            .filter(|addr| !internal.contains(addr) && seen.insert(*addr))
+            .collect();
+        FusedAdvice { parts, kept, inputs, outputs }
+    }
+
+    /// Values which are passed between the parts without being stored.
+    pub fn kept(&self) -> &HashSet<S::RawAddr> {
+        &self.kept
+    }
+}
+
+impl<S: Storage> RTAdvice<S> for FusedAdvice<S> {
+    fn inputs(&self) -> Vec<S::RawAddr> {
+        self.inputs.clone()
+    }
+
+    fn outputs(&self) -> Vec<S::RawAddr> {
+        self.outputs.clone()
+    }
+
+    fn call(&self, storage: &mut S) {
+        let mut local = LocalValues::new(&self.kept);
+        for part in self.parts.iter() {
+            part.call_local(storage, &mut local);
+        }
+    }
+
+    fn kinds(&self) -> Option<Vec<KindCall<S::RawAddr>>> {
+        let parts: Option<Vec<_>> = self.parts.iter().map(|part| part.kinds()).collect();
+        parts.map(|parts| parts.concat())
+    }
+}
+
+/// Values of the graph which are read by the backend: outputs and committed values.
+fn read_by_backend<S: Storage>(graph: &RTGraph<S>) -> HashSet<S::RawAddr> {
+    graph.outputs().iter().chain(graph.groups().iter().flatten()).copied().collect()
+}
+
+/// What advice fusion has done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FusionReport {
+    /// Fused advices created.
+    pub groups: usize,
+    /// Decrease in the amount of advices.
+    pub removed: usize,
+    /// Values passed inside fused advices without being stored.
+    pub kept: usize,
+}
+
+/// Longest common prefix of namespaces, by whole names.
+fn common_namespace<'a>(mut namespaces: impl Iterator<Item = &'a str>) -> String {
+    let mut prefix: Vec<&str> = namespaces.next().map_or(vec![], |ns| ns.split('/').collect());
+    for ns in namespaces {
+        let len = prefix.iter().zip(ns.split('/')).take_while(|(a, b)| *a == b).count();
+        prefix.truncate(len);
+    }
+    prefix.join("/")
+}
+
+/// Fuses every advice whose outputs are read by a single other advice (its consumer) into the consumer, transitively.
+/// Advices are not fused into consumers which can only run in a later round (see round_bounds), so that values
+/// committed before a challenge do not wait for it. Each fused advice takes the place of the last consumer, and the
+/// common part of namespaces of its parts. Values passed inside a fused advice are not stored unless the backend or other
+/// advices read them (see FusedAdvice::new). Resets stages of the graph. Expects a valid graph (see validate); panics
+/// on cycles.
+pub fn fuse_advices<S: Storage + 'static>(graph: &mut RTGraph<S>) -> FusionReport {
+    let rounds = round_bounds(graph);
+    let read_by_backend = read_by_backend(graph);
+    graph._set_stages(vec![]);
+    let nodes = std::mem::take(graph.advices_mut());
+    let n = nodes.len();
+
+    let mut readers: HashMap<S::RawAddr, Vec<usize>> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        for addr in node.advice.inputs() {
+            readers.entry(addr).or_default().push(i);
+        }
+    }
+    let next: Vec<Option<usize>> = nodes.iter().enumerate().map(|(i, node)| {
+        let consumers: HashSet<usize> = node.advice.outputs().iter()
+            .flat_map(|addr| readers.get(addr).into_iter().flatten().copied())
+            .collect();
+        match consumers.into_iter().collect::<Vec<_>>()[..] {
+            [j] if j != i && rounds[j].0 == rounds[i].0 => Some(j),
+            _ => None,
+        }
+    }).collect();
+
+    // Members of each group, with their distance to the root (the last consumer).
+    let mut groups: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+    for i in 0..n {
+        let (mut root, mut depth) = (i, 0);
+        while let Some(j) = next[root] {
+            root = j;
+            depth += 1;
+            assert!(depth <= n, "advices depend on each other cyclically");
+        }
+        groups.entry(root).or_default().push((depth, i));
+    }
+
+    let mut report = FusionReport::default();
+    let mut nodes: Vec<Option<RTNode<S>>> = nodes.into_iter().map(Some).collect();
+    let mut advices = vec![];
+    for root in 0..n {
+        let Some(mut members) = groups.remove(&root) else { continue };
+        if members.len() == 1 {
+            advices.push(nodes[root].take().unwrap());
+            continue;
+        }
+        // Deeper members go first: every member is deeper than its consumer.
+        members.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        let parts: Vec<RTNode<S>> = members.iter().map(|(_, i)| nodes[*i].take().unwrap()).collect();
+        let namespace = common_namespace(parts.iter().map(|part| part.namespace.as_str()));
+        let group: HashSet<usize> = members.iter().map(|(_, i)| *i).collect();
+        let exposed: HashSet<S::RawAddr> = parts.iter()
+            .flat_map(|part| part.advice.outputs())
+            .filter(|addr| read_by_backend.contains(addr) || readers.get(addr).into_iter().flatten().any(|j| !group.contains(j)))
+            .collect();
+        let advice = Box::new(FusedAdvice::new(parts.into_iter().map(|part| part.advice).collect(), &exposed));
+        report.kept += advice.kept().len();
+        report.groups += 1;
+        report.removed += members.len() - 1;
+        advices.push(RTNode { advice, namespace });
+    }
+
+    *graph.advices_mut() = advices;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Inputs, Namespaces, ToRawAddr},
+        middleend::{compiler::compile, validate::validate},
+        test_utils::{execute, square, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fuse_advices() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let e = c.in_namespace("chain", |c| {
+            let b = c.in_namespace("square", |c| square(c, a));
+            let d = c.advise_sigs(&[b], 1, |x| vec![x[0].double()])[0];
+            c.advise_sigs(&[d], 1, |x| vec![x[0] + Fr::one()])[0]
+        });
+        let p = c.advise_sigs(&[e], 1, |x| vec![x[0].double()])[0];
+        let q = c.advise_sigs(&[e, a], 1, |x| vec![x[0] + x[1]])[0];
+
+        let mut s = TestStorage::default();
+        let (mut graph, mapping) = compile(&c, &mut s);
+        assert_eq!(fuse_advices(&mut graph), FusionReport { groups: 1, removed: 2, kept: 0 });
+        assert_eq!(graph.advices().len(), 3);
+        assert_eq!(graph.advices()[0].namespace, "chain");
+        assert!(validate(&graph).is_empty());
+
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&p.to_raw_addr()]), &Fr::from(38));
+        assert_eq!(s.get(&mapping[&q.to_raw_addr()]), &Fr::from(22));
+    }
+
+    #[test]
+    fn test_fuse_advices_keeps_intermediates() {
+        // a -> t -> u -> e, where only t is neither committed nor read outside of the chain.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let t = c.advise_dependent(&[a], 1, |x| vec![x[0].double()])[0];
+        let u = c.advise_sigs(&[t], 1, |x| vec![x[0] + Fr::one()])[0];
+        let e = c.advise_dependent(&[u], 1, |x| vec![x[0].square()])[0];
+        let f = c.advise_dependent(&[e], 1, |x| vec![x[0].double()])[0];
+        let g = c.advise_dependent(&[e, a], 1, |x| vec![x[0] + x[1]])[0];
+
+        let mut s = TestStorage::default();
+        let (mut graph, mapping) = compile(&c, &mut s);
+        assert_eq!(fuse_advices(&mut graph), FusionReport { groups: 1, removed: 2, kept: 1 });
+        let addr = |sig: TSig| mapping[&sig.to_raw_addr()];
+        assert_eq!(graph.advices()[0].advice.outputs(), vec![addr(u), addr(e)]);
+        assert!(validate(&graph).is_empty());
+
+        s.put(&addr(a), Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&addr(f)), &Fr::from(98));
+        assert_eq!(s.get(&addr(g)), &Fr::from(52));
+        // Everything but t is stored.
+        assert_eq!(s.data.iter().flatten().count(), 5);
+        assert_eq!(s.data[addr(t)], None);
+    }
+}
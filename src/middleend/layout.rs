@@ -0,0 +1,90 @@
+use std::{any::TypeId, collections::HashMap};
+
+use crate::{
+    backend::storage::RawAllocator,
+    circuit::{AliasFlag, ChallengeFlag, CommitmentGroups, SignalFlag},
+};
+
+use super::compiler::AddrMapping;
+
+/// Allocates storage for live values only, densely and in an order which keeps related values together: committed
+/// values by commitment group, then challenges by the group they are derived after, then the rest. Within each
+/// part, values are grouped by type (in order of first appearance) and then kept in order of allocation.
+/// Addresses which are not variables (flags-only, dead or merged) get no storage; merged values (see AliasFlag)
+/// share storage with the value they were merged into. Use with compile_with.
+pub fn layout<C, S>(c: &C, s: &mut S) -> AddrMapping<C, S>
+where
+    C: SignalFlag + AliasFlag + CommitmentGroups + ChallengeFlag,
+    S: RawAllocator,
+{
+    let mut types: HashMap<TypeId, usize> = HashMap::new();
+    let mut live = vec![];
+    for (i, addr) in c.raw_addrs().into_iter().enumerate() {
+        if !c.is_var(addr) {
+            continue;
+        }
+        let ty = c.inner_type(addr);
+        let next = types.len();
+        let type_rank = *types.entry(ty).or_insert(next);
+        let part = match (c.group(addr), c.challenge_after(addr)) {
+            (Some(group), _) => (0, group),
+            (None, Some(after)) => (1, after),
+            (None, None) => (2, 0),
+        };
+        live.push(((part, type_rank, i), addr, ty));
+    }
+    live.sort_by_key(|(key, _, _)| *key);
+
+    let mut mapping: AddrMapping<C, S> = live.into_iter().map(|(_, addr, ty)| (addr, s.allocate_raw(ty))).collect();
+    for addr in c.raw_addrs() {
+        if c.alias(addr).is_some() {
+            let slot = mapping[&c.resolve(addr)];
+            mapping.insert(addr, slot);
+        }
+    }
+    mapping
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Inputs, Signals, ToRawAddr, VariableFlag},
+        middleend::{compiler::compile_with, cse::eliminate_common_subexpressions, validate::validate},
+        test_utils::{execute, square, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let d = c.advise_dependent(&[a], 1, |x| vec![x[0].double()])[0];
+        let b1 = square(&mut c, a);
+        let b2 = square(&mut c, a);
+        let dead = c._alloc_sig_dependent::<Fr>();
+        c._set_var_flag(dead.to_raw_addr(), false);
+        c.group = 1;
+        let e = c.advise_sigs(&[d, b2], 1, |x| vec![x[0] + x[1]])[0];
+        eliminate_common_subexpressions(&mut c);
+
+        let mut s = TestStorage::default();
+        let mapping = layout(&c, &mut s);
+        assert_eq!(s.data.len(), 4);
+        assert!(!mapping.contains_key(&dead.to_raw_addr()));
+        assert_eq!(mapping[&a.to_raw_addr()], 0);
+        assert_eq!(mapping[&b1.to_raw_addr()], 1);
+        assert_eq!(mapping[&b2.to_raw_addr()], 1);
+        assert_eq!(mapping[&e.to_raw_addr()], 2);
+        assert_eq!(mapping[&d.to_raw_addr()], 3);
+
+        let graph = compile_with(&c, &mut s, &mapping);
+        assert!(validate(&graph).is_empty());
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&e.to_raw_addr()]), &Fr::from(15));
+    }
+}
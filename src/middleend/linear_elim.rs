@@ -0,0 +1,201 @@
+use std::{collections::HashSet, fmt::{self, Display}};
+
+use ff::Field;
+
+use crate::circuit::{
+    ChallengeFlag, Circuit, CommitmentGroups, ConstantFlag, Constraint, ConstraintLog, InputFlag, Lookups,
+    PrimarySignalFlag, PublicFlag,
+};
+
+/// What linear elimination has done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinearEliminationReport {
+    /// Signals which were substituted away and are no longer committed. Each of them removes one constraint.
+    pub signals: usize,
+}
+
+/// Constraint which becomes unsatisfiable once linear definitions are substituted, by its index in the constraint log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinearEliminationError(pub usize);
+
+impl Display for LinearEliminationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "constraint {} is unsatisfiable after linear elimination", self.0)
+    }
+}
+
+/// Finds linear constraints coeff * x + sum_i coeff_i * sig_i + const = 0, where x is a committed signal which can be
+/// eliminated (see eliminable), substitutes x by the linear combination of others in all other constraints, and
+/// removes the defining constraint. Eliminated signals are no longer committed, as if they were allocated by
+/// _alloc_sig_dependent; their values are still computed by advices.
+/// Returns an error if some constraint becomes unsatisfiable, leaving constraints and signal flags unchanged.
+pub fn eliminate_linear<C>(c: &mut C) -> Result<LinearEliminationReport, LinearEliminationError>
+where
+    C: ConstraintLog + Lookups + PrimarySignalFlag + CommitmentGroups + InputFlag + PublicFlag + ChallengeFlag + ConstantFlag,
+{
+    // Substituting linear definitions only rewrites linear terms, so the signals kept stay the same.
+    let kept: HashSet<C::RawAddr> = c.lookups().iter().flat_map(|lookup| lookup.sigs.iter().copied())
+        .chain(c.constraints().iter().flat_map(|constraint| constraint.terms.iter())
+            .filter(|(_, sigs)| sigs.len() > 1)
+            .flat_map(|(_, sigs)| sigs.iter().copied()))
+        .collect();
+    // Constraints are rewritten aside, with indices in the log for errors, and only written back once all are valid.
+    let mut constraints: Vec<(usize, Constraint<C>)> = c.constraints().iter().cloned().enumerate().collect();
+    let mut eliminated = vec![];
+
+    let mut i = 0;
+    while i < constraints.len() {
+        let Some((x, definition)) = definition(c, &constraints[i].1, &kept) else {
+            i += 1;
+            continue;
+        };
+        constraints.remove(i);
+
+        let mut substituted = Vec::with_capacity(constraints.len());
+        for (index, constraint) in constraints {
+            let constraint = substitute(constraint, x, &definition);
+            if constraint.is_trivial() {
+                continue;
+            }
+            if constraint.is_unsatisfiable() {
+                return Err(LinearEliminationError(index));
+            }
+            substituted.push((index, constraint));
+        }
+        constraints = substituted;
+        eliminated.push(x);
+    }
+
+    *c.constraints_mut() = constraints.into_iter().map(|(_, constraint)| constraint).collect();
+    for x in eliminated.iter() {
+        c._set_primary_flag(*x, false);
+        c._set_group(*x, None);
+    }
+    Ok(LinearEliminationReport { signals: eliminated.len() })
+}
+
+/// Checks whether a signal can be removed from the witness: it is committed, is not provided externally, is not
+/// exposed, and is not kept, i.e. used in lookups (which can not be applied to linear combinations) or in nonlinear
+/// terms (where substitution would multiply the amount of terms and raise the degree of others).
+fn eliminable<C>(c: &C, addr: C::RawAddr, kept: &HashSet<C::RawAddr>) -> bool
+where
+    C: PrimarySignalFlag + InputFlag + PublicFlag + ChallengeFlag + ConstantFlag,
+{
+    c.is_primary(addr)
+        && !c.is_input(addr)
+        && !c.is_public(addr)
+        && c.challenge_after(addr).is_none()
+        && !c.is_const(addr)
+        && !kept.contains(&addr)
+}
+
+/// Linear combination of signals, with None standing for the constant term.
+type Combination<C> = Vec<(<C as Circuit>::F, Option<<C as Circuit>::RawAddr>)>;
+
+/// If the constraint is linear and defines an eliminable signal x, returns x and its definition.
+fn definition<C>(c: &C, constraint: &Constraint<C>, kept: &HashSet<C::RawAddr>) -> Option<(C::RawAddr, Combination<C>)>
+where
+    C: PrimarySignalFlag + InputFlag + PublicFlag + ChallengeFlag + ConstantFlag,
+{
+    if constraint.degree() > 1 {
+        return None;
+    }
+    let (coeff, x) = constraint.terms.iter().find_map(|(coeff, sigs)| {
+        let x = *sigs.first()?;
+        let occurrences = constraint.terms.iter().filter(|(_, sigs)| sigs.contains(&x)).count();
+        (occurrences == 1 && !bool::from(coeff.is_zero()) && eliminable(c, x, kept)).then_some((*coeff, x))
+    })?;
+    let factor = -coeff.invert().unwrap();
+    let definition = constraint.terms.iter()
+        .filter(|(_, sigs)| !sigs.contains(&x))
+        .map(|(other, sigs)| (*other * factor, sigs.first().copied()))
+        .collect();
+    Some((x, definition))
+}
+
+/// Replaces every occurrence of x in the constraint by its definition.
+fn substitute<C: Circuit>(constraint: Constraint<C>, x: C::RawAddr, definition: &Combination<C>) -> Constraint<C> {
+    if !constraint.terms.iter().any(|(_, sigs)| sigs.contains(&x)) {
+        return constraint;
+    }
+
+    let mut terms = vec![];
+    for (coeff, sigs) in constraint.terms {
+        let rest: Vec<_> = sigs.iter().copied().filter(|sig| *sig != x).collect();
+        let mut expanded = vec![(coeff, rest)];
+        for _ in 0..(sigs.len() - expanded[0].1.len()) {
+            expanded = expanded.into_iter()
+                .flat_map(|(coeff, sigs)| definition.iter().map(move |(other, sig)| {
+                    (coeff * other, sigs.iter().copied().chain(*sig).collect())
+                }))
+                .collect();
+        }
+        terms.extend(expanded);
+    }
+
+    Constraint { terms, namespace: constraint.namespace }.simplify()
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, CommitmentGroups, Constraint, ConstraintLog, Inputs, PrimarySignalFlag, ToRawAddr, _Into},
+        test_utils::TestCircuit,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_eliminate_linear() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| vec![x[0].double() + Fr::one()])[0];
+        let e = c.advise_sigs(&[b], 1, |x| vec![x[0] + Fr::from(3)])[0];
+        let d = c.advise_sigs(&[a, e], 1, |x| vec![x[0] * x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::from(2), &[a._into()]).term(-Fr::one(), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[e._into()]).term(-Fr::one(), &[b._into()]).term(-Fr::from(3), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[d._into()]).term(-Fr::one(), &[a._into(), e._into()]));
+
+        assert_eq!(eliminate_linear(&mut c), Ok(LinearEliminationReport { signals: 1 }));
+        assert!(!c.is_primary(b.to_raw_addr()));
+        assert_eq!(c.group(b.to_raw_addr()), None);
+        assert!(c.is_primary(e.to_raw_addr()));
+        assert_eq!(c.constraints().len(), 2);
+        assert_eq!(c.constraints()[0].terms, vec![
+            (Fr::one(), vec![e.to_raw_addr()]),
+            (-Fr::from(2), vec![a.to_raw_addr()]),
+            (-Fr::from(4), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn test_eliminate_linear_keeps_nonlinear() {
+        // b occurs in a product, so substituting it would expand the product.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| vec![x[0].double() + Fr::one()])[0];
+        let d = c.advise_sigs(&[a, b], 1, |x| vec![x[0] * x[1] * x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::from(2), &[a._into()]).term(-Fr::one(), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[d._into()]).term(-Fr::one(), &[a._into(), b._into(), b._into()]));
+
+        assert_eq!(eliminate_linear(&mut c), Ok(LinearEliminationReport { signals: 0 }));
+        assert!(c.is_primary(b.to_raw_addr()));
+        assert_eq!(c.constraints().len(), 2);
+    }
+
+    #[test]
+    fn test_eliminate_linear_unsatisfiable() {
+        // b = 2a + 1 and b = 2a + 2 contradict each other once b is substituted.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| vec![x[0].double() + Fr::one()])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::from(2), &[a._into()]).term(-Fr::one(), &[]));
+        c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::from(2), &[a._into()]).term(-Fr::from(2), &[]));
+
+        assert_eq!(eliminate_linear(&mut c), Err(LinearEliminationError(1)));
+        assert!(c.is_primary(b.to_raw_addr()));
+        assert_eq!(c.constraints().len(), 2);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod bounds;
+pub mod compiler;
+pub mod const_fold;
+pub mod cse;
+pub mod dead_advices;
+pub mod degree;
+pub mod dot;
+pub(crate) mod escape;
+pub mod fusion;
+pub mod layout;
+pub mod linear_elim;
+pub mod passes;
+pub mod rounds;
+pub mod serialize;
+pub mod stats;
+pub mod underconstrained;
+pub mod validate;
@@ -0,0 +1,495 @@
+use std::{fmt::{self, Display}, time::{Duration, Instant}};
+
+use crate::{
+    backend::{api::RTGraph, storage::{RawAllocator, ReaderOf, Storage, WriterOf}},
+    circuit::{
+        AdviceLog, AliasFlag, ChallengeFlag, CommitmentGroups, ConstantFlag, ConstraintLog, HasSigtype, InputFlag,
+        Lookups, PrimarySignalFlag, PublicFlag, RangeBound, Signals,
+    },
+};
+
+use super::{
+    bounds::propagate_bounds,
+    compiler::{compile, compile_with, AddrMapping},
+    const_fold::fold_constants,
+    cse::eliminate_common_subexpressions,
+    dead_advices::eliminate_dead_advices,
+    degree::{reduce_degree, DegreeConfig},
+    fusion::fuse_advices,
+    layout::layout,
+    linear_elim::eliminate_linear,
+    rounds::partition_rounds,
+    underconstrained::find_underconstrained,
+    validate::{validate, validate_circuit},
+};
+
+/// Execution graph of a circuit, with the mapping of its addresses into storage.
+pub type Compiled<C> = (RTGraph<<C as AdviceLog>::Storage>, AddrMapping<C, <C as AdviceLog>::Storage>);
+
+/// Named counters reported by a pass, e.g. ("advices", 3) for 3 removed advices.
+pub type Stats = Vec<(&'static str, usize)>;
+
+/// Pass which could not transform the IR, e.g. as some constraint turned out unsatisfiable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassError {
+    pub pass: &'static str,
+    pub message: String,
+}
+
+impl Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pass {} failed: {}", self.pass, self.message)
+    }
+}
+
+/// Transformation or analysis of an IR: either the circuit C, or the execution graph RTGraph<S>.
+pub trait Pass<IR> {
+    fn name(&self) -> &'static str;
+    /// Transforms the IR in place and returns statistics of what was done, or describes why the IR can not be
+    /// transformed. The IR should be left unchanged in this case.
+    fn run(&mut self, ir: &mut IR) -> Result<Stats, String>;
+}
+
+/// Optimization level, determining the passes of PassManager::pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations, only degree reduction, as the degree bound is a requirement of the backend.
+    O0,
+    /// Cheap passes which remove redundant advices: CSE and dead advice elimination, with degree reduction in between.
+    /// The graph is staged by rounds. Underconstrained signals are counted first, see UnderconstrainedCheck.
+    O1,
+    /// All optimizations: constant folding, CSE, bound propagation, linear elimination, degree reduction, dead advice
+    /// elimination and advice fusion. The graph is staged by rounds. Underconstrained signals are counted first, see
+    /// UnderconstrainedCheck.
+    O2,
+}
+
+/// What a pass has done, and how long it took.
+#[derive(Clone, Debug)]
+pub struct PassRecord {
+    pub name: &'static str,
+    pub duration: Duration,
+    pub stats: Stats,
+}
+
+/// Sequence of passes over the circuit, followed by compilation and passes over the execution graph.
+pub struct PassManager<C: AdviceLog> {
+    circuit_passes: Vec<Box<dyn Pass<C>>>,
+    graph_passes: Vec<Box<dyn Pass<RTGraph<C::Storage>>>>,
+    verify: bool,
+    compact: bool,
+    records: Vec<PassRecord>,
+}
+
+impl<C: AdviceLog> PassManager<C> {
+    /// Creates an empty pass manager. Verification and compact layout are enabled by default.
+    pub fn new() -> Self {
+        PassManager { circuit_passes: vec![], graph_passes: vec![], verify: true, compact: true, records: vec![] }
+    }
+
+    /// Enables or disables verification of the IR after every pass.
+    pub fn set_verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Chooses between the compact storage layout (see layout) and allocation in order of addresses (see allocate).
+    pub fn set_compact_layout(&mut self, compact: bool) -> &mut Self {
+        self.compact = compact;
+        self
+    }
+
+    pub fn add_pass(&mut self, pass: impl Pass<C> + 'static) -> &mut Self {
+        self.circuit_passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn add_graph_pass(&mut self, pass: impl Pass<RTGraph<C::Storage>> + 'static) -> &mut Self {
+        self.graph_passes.push(Box::new(pass));
+        self
+    }
+
+    /// Records of the passes executed by the last run, in order of execution.
+    pub fn records(&self) -> &Vec<PassRecord> {
+        &self.records
+    }
+
+    /// Names of registered passes, in order of execution.
+    pub fn passes(&self) -> Vec<&'static str> {
+        self.circuit_passes.iter().map(|pass| pass.name())
+            .chain(self.graph_passes.iter().map(|pass| pass.name()))
+            .collect()
+    }
+}
+
+impl<C: AdviceLog> Default for PassManager<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> PassManager<C>
+where
+    C: AdviceLog + ConstraintLog + Lookups + PrimarySignalFlag + CommitmentGroups + InputFlag + PublicFlag + ChallengeFlag
+        + ConstantFlag + AliasFlag + RangeBound + Signals + 'static,
+    C::Config: DegreeConfig + HasSigtype<C::F>,
+    C::Storage: RawAllocator + ReaderOf<C::F> + WriterOf<C::F> + Default + 'static,
+{
+    /// Creates a pass manager running the passes of the optimization level.
+    pub fn pipeline(level: OptLevel) -> Self {
+        let mut pm = Self::new();
+        match level {
+            OptLevel::O0 => {
+                pm.add_pass(DegreeReduction);
+            }
+            OptLevel::O1 => {
+                pm.add_pass(UnderconstrainedCheck)
+                    .add_pass(Cse)
+                    .add_pass(DegreeReduction)
+                    .add_pass(DeadAdvices)
+                    .add_graph_pass(RoundPartition);
+            }
+            OptLevel::O2 => {
+                // Bounds are propagated through the defining constraints before linear elimination removes them.
+                pm.add_pass(UnderconstrainedCheck)
+                    .add_pass(ConstantFolding)
+                    .add_pass(Cse)
+                    .add_pass(BoundPropagation)
+                    .add_pass(LinearElimination)
+                    .add_pass(DegreeReduction)
+                    .add_pass(DeadAdvices)
+                    .add_graph_pass(AdviceFusion)
+                    .add_graph_pass(RoundPartition);
+            }
+        }
+        pm
+    }
+}
+
+impl<C> PassManager<C>
+where
+    C: AdviceLog + ConstraintLog + Lookups + ChallengeFlag + CommitmentGroups + InputFlag + PublicFlag + ConstantFlag
+        + AliasFlag,
+    C::Storage: RawAllocator,
+{
+    /// Runs circuit passes, stopping at the first one which fails. Panics if verification is enabled and some pass
+    /// leaves the circuit invalid.
+    pub fn run_circuit_passes(&mut self, c: &mut C) -> Result<(), PassError> {
+        self.records.clear();
+        for pass in self.circuit_passes.iter_mut() {
+            let record = timed(pass.as_mut(), c)?;
+            if self.verify {
+                let errors = validate_circuit(c);
+                assert!(errors.is_empty(), "circuit is invalid after pass {}: {}", record.name, errors[0]);
+            }
+            self.records.push(record);
+        }
+        Ok(())
+    }
+
+    /// Runs circuit passes, compiles the circuit into storage s and runs graph passes. Returns the error of the first
+    /// pass which fails.
+    pub fn run(&mut self, c: &mut C, s: &mut C::Storage) -> Result<Compiled<C>, PassError> {
+        self.run_circuit_passes(c)?;
+        let (mut graph, mapping) = if self.compact {
+            let mapping = layout(c, s);
+            (compile_with(c, s, &mapping), mapping)
+        } else {
+            compile(c, s)
+        };
+        if self.verify {
+            let errors = validate(&graph);
+            assert!(errors.is_empty(), "compiled graph is invalid: {}", errors[0]);
+        }
+        for pass in self.graph_passes.iter_mut() {
+            let record = timed(pass.as_mut(), &mut graph)?;
+            if self.verify {
+                let errors = validate(&graph);
+                assert!(errors.is_empty(), "graph is invalid after pass {}: {}", record.name, errors[0]);
+            }
+            self.records.push(record);
+        }
+        Ok((graph, mapping))
+    }
+}
+
+fn timed<IR>(pass: &mut dyn Pass<IR>, ir: &mut IR) -> Result<PassRecord, PassError> {
+    let start = Instant::now();
+    let stats = pass.run(ir).map_err(|message| PassError { pass: pass.name(), message })?;
+    Ok(PassRecord { name: pass.name(), duration: start.elapsed(), stats })
+}
+
+/// See find_underconstrained. Analysis, changes nothing. Runs on the constraints as built, as optimizations (linear
+/// elimination in particular) remove constraints which determine signals.
+pub struct UnderconstrainedCheck;
+
+impl<C> Pass<C> for UnderconstrainedCheck
+where
+    C: AdviceLog + ConstraintLog + Lookups + ChallengeFlag + ConstantFlag + PublicFlag,
+{
+    fn name(&self) -> &'static str {
+        "underconstrained"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        Ok(vec![("signals", find_underconstrained(c).len())])
+    }
+}
+
+/// See eliminate_dead_advices. Committed signals are kept.
+pub struct DeadAdvices;
+
+impl<C> Pass<C> for DeadAdvices
+where
+    C: AdviceLog + ConstraintLog + Lookups + CommitmentGroups + PublicFlag + InputFlag + ChallengeFlag,
+{
+    fn name(&self) -> &'static str {
+        "dead-advices"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = eliminate_dead_advices(c, false);
+        Ok(vec![("advices", report.advices), ("slots", report.slots)])
+    }
+}
+
+/// See fold_constants. Values are computed in a fresh scratch storage. Fails if some constraint or lookup is
+/// unsatisfiable.
+pub struct ConstantFolding;
+
+impl<C> Pass<C> for ConstantFolding
+where
+    C: AdviceLog + ConstantFlag + ConstraintLog + Lookups + PrimarySignalFlag,
+    C::Storage: RawAllocator + ReaderOf<C::F> + Default,
+{
+    fn name(&self) -> &'static str {
+        "const-fold"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = fold_constants(c, &mut C::Storage::default()).map_err(|error| error.to_string())?;
+        Ok(vec![
+            ("advices", report.advices),
+            ("constants", report.constants),
+            ("simplified", report.simplified),
+            ("removed", report.removed),
+            ("lookups", report.lookups),
+        ])
+    }
+}
+
+/// See eliminate_common_subexpressions.
+pub struct Cse;
+
+impl<C> Pass<C> for Cse
+where
+    C: AdviceLog + ConstraintLog + Lookups + AliasFlag + PrimarySignalFlag + CommitmentGroups + PublicFlag,
+{
+    fn name(&self) -> &'static str {
+        "cse"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = eliminate_common_subexpressions(c);
+        Ok(vec![
+            ("advices", report.advices),
+            ("merged", report.merged),
+            ("constraints", report.constraints),
+            ("lookups", report.lookups),
+        ])
+    }
+}
+
+/// See eliminate_linear. Fails if some constraint becomes unsatisfiable.
+pub struct LinearElimination;
+
+impl<C> Pass<C> for LinearElimination
+where
+    C: ConstraintLog + Lookups + PrimarySignalFlag + CommitmentGroups + InputFlag + PublicFlag + ChallengeFlag + ConstantFlag,
+{
+    fn name(&self) -> &'static str {
+        "linear-elim"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = eliminate_linear(c).map_err(|error| error.to_string())?;
+        Ok(vec![("signals", report.signals)])
+    }
+}
+
+/// See reduce_degree. A part of every pipeline, as the degree bound is a requirement of the backend.
+pub struct DegreeReduction;
+
+impl<C> Pass<C> for DegreeReduction
+where
+    C: AdviceLog + ConstraintLog + Signals + 'static,
+    C::Config: DegreeConfig + HasSigtype<C::F>,
+    C::Storage: ReaderOf<C::F> + WriterOf<C::F> + 'static,
+{
+    fn name(&self) -> &'static str {
+        "degree"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = reduce_degree(c);
+        Ok(vec![("rewritten", report.rewritten), ("signals", report.cost.signals)])
+    }
+}
+
+/// See propagate_bounds. Analysis, only changes RangeBound.
+pub struct BoundPropagation;
+
+impl<C> Pass<C> for BoundPropagation
+where
+    C: ConstraintLog + Lookups + RangeBound,
+{
+    fn name(&self) -> &'static str {
+        "bounds"
+    }
+
+    fn run(&mut self, c: &mut C) -> Result<Stats, String> {
+        let report = propagate_bounds(c);
+        Ok(vec![
+            ("redundant", report.redundant.len()),
+            ("wraps", report.wraps.len()),
+            ("tightened", report.tightened),
+            ("checked", report.checked.len()),
+        ])
+    }
+}
+
+/// See fuse_advices.
+pub struct AdviceFusion;
+
+impl<S: Storage + 'static> Pass<RTGraph<S>> for AdviceFusion {
+    fn name(&self) -> &'static str {
+        "fusion"
+    }
+
+    fn run(&mut self, graph: &mut RTGraph<S>) -> Result<Stats, String> {
+        let report = fuse_advices(graph);
+        Ok(vec![("groups", report.groups), ("removed", report.removed), ("kept", report.kept)])
+    }
+}
+
+/// See partition_rounds. Fails if some advice can not be assigned a round.
+pub struct RoundPartition;
+
+impl<S: Storage> Pass<RTGraph<S>> for RoundPartition {
+    fn name(&self) -> &'static str {
+        "rounds"
+    }
+
+    fn run(&mut self, graph: &mut RTGraph<S>) -> Result<Stats, String> {
+        let report = partition_rounds(graph)
+            .map_err(|errors| format!("advices can not be partitioned into rounds: {}", errors[0]))?;
+        Ok(vec![("rounds", report.sizes.len())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, Lookups, Publics, ToRawAddr, _Into},
+        gadgets::traits::bigint_arith::range_table,
+        test_utils::{check_constraints, execute, square, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    struct CountConstraints;
+
+    impl Pass<TestCircuit> for CountConstraints {
+        fn name(&self) -> &'static str {
+            "count"
+        }
+
+        fn run(&mut self, c: &mut TestCircuit) -> Result<Stats, String> {
+            Ok(vec![("constraints", c.constraints().len())])
+        }
+    }
+
+    #[test]
+    fn test_pass_manager() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b1 = square(&mut c, a);
+        let b2 = square(&mut c, a);
+        let d = c.advise_sigs(&[b1, b2], 1, |x| vec![x[0] + x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[d._into()]).term(-Fr::one(), &[b1._into()]).term(-Fr::one(), &[b2._into()]));
+        c.make_public(d);
+        c.advise_dependent(&[d], 1, |x| x);
+
+        let mut pm = PassManager::pipeline(OptLevel::O2);
+        pm.add_pass(CountConstraints);
+        assert_eq!(pm.passes(), vec![
+            "underconstrained", "const-fold", "cse", "bounds", "linear-elim", "degree", "dead-advices", "count", "fusion",
+            "rounds",
+        ]);
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = pm.run(&mut c, &mut s).unwrap();
+        let stats: Vec<_> = pm.records().iter().map(|record| (record.name, record.stats.clone())).collect();
+        // Only the dependent signal is underconstrained: the check runs before linear elimination removes the
+        // constraint determining b1.
+        assert_eq!(stats[0], ("underconstrained", vec![("signals", 1)]));
+        assert_eq!(stats[2], ("cse", vec![("advices", 1), ("merged", 1), ("constraints", 1), ("lookups", 0)]));
+        assert_eq!(stats[4], ("linear-elim", vec![("signals", 1)]));
+        assert_eq!(stats[6], ("dead-advices", vec![("advices", 1), ("slots", 1)]));
+        assert_eq!(stats[7], ("count", vec![("constraints", 1)]));
+
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&d.to_raw_addr()]), &Fr::from(18));
+        check_constraints(&c, &s, &mapping);
+    }
+
+    #[test]
+    fn test_pipeline_bounds_and_degree() {
+        // x = l0 + 16 * l1 is implied to be below 256 by the checks of the limbs, and y = x^3 exceeds degree 2.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let limbs = [c.alloc_input::<Fr>(), c.alloc_input::<Fr>()];
+        let small = range_table(&mut c, 16);
+        let large = range_table(&mut c, 256);
+        c.lookup(small, &[limbs[0]]);
+        c.lookup(small, &[limbs[1]]);
+        let x = c.advise_sigs(&limbs, 1, |x| vec![x[0] + Fr::from(16) * x[1]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into()]).term(-Fr::one(), &[limbs[0]._into()]).term(-Fr::from(16), &[limbs[1]._into()]));
+        c.lookup(large, &[x]);
+        let y = c.advise_sigs(&[x], 1, |x| vec![x[0] * x[0] * x[0]])[0];
+        c.constrain(Constraint::new().term(Fr::one(), &[y._into()]).term(-Fr::one(), &[x._into(), x._into(), x._into()]));
+        c.make_public(y);
+
+        let mut pm = PassManager::pipeline(OptLevel::O2);
+        let mut s = TestStorage::default();
+        let (graph, mapping) = pm.run(&mut c, &mut s).unwrap();
+        let stats: Vec<_> = pm.records().iter().map(|record| (record.name, record.stats.clone())).collect();
+        assert_eq!(stats[3], ("bounds", vec![("redundant", 1), ("wraps", 0), ("tightened", 0), ("checked", 2)]));
+        assert_eq!(stats[5], ("degree", vec![("rewritten", 1), ("signals", 1)]));
+        assert!(c.constraints().iter().all(|constraint| constraint.degree() <= 2));
+
+        s.put(&mapping[&limbs[0].to_raw_addr()], Fr::from(3));
+        s.put(&mapping[&limbs[1].to_raw_addr()], Fr::from(1));
+        execute(&graph, &mut s);
+        assert_eq!(s.get(&mapping[&y.to_raw_addr()]), &Fr::from(19 * 19 * 19));
+        check_constraints(&c, &s, &mapping);
+    }
+
+    #[test]
+    fn test_pass_error() {
+        // The constant 2 can not be 3.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let k = c.advise_dependent(&[], 1, |_| vec![Fr::from(2)])[0];
+        c._set_const_flag(k.to_raw_addr(), true);
+        c.constrain(Constraint::new().term(Fr::one(), &[k._into()]).term(-Fr::from(3), &[]));
+
+        let mut pm = PassManager::pipeline(OptLevel::O2);
+        let error = pm.run(&mut c, &mut TestStorage::default()).err().unwrap();
+        assert_eq!(error, PassError { pass: "const-fold", message: "constraint 0 is unsatisfiable".to_string() });
+        assert_eq!(pm.records().len(), 1);
+        assert_eq!(c.constraints().len(), 1);
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display}};
+
+use crate::backend::{api::{RTGraph, RTNode}, storage::Storage};
+
+use super::validate::AdviceRef;
+
+/// Advice which contributes to a commitment group, but depends on a challenge derived after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundError {
+    pub advice: usize,
+    pub namespace: String,
+    /// Earliest round, determined by challenges read by the advice and by advices it depends on.
+    pub earliest: usize,
+    /// Latest round, determined by commitment groups written by the advice and by advices depending on it.
+    pub latest: usize,
+}
+
+impl Display for RoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} depends on challenges available from round {}, but contributes to commitment group {}",
+            AdviceRef(self.advice, &self.namespace), self.earliest, self.latest,
+        )
+    }
+}
+
+/// What round partitioning has done.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundReport {
+    /// Amount of advices in each round.
+    pub sizes: Vec<usize>,
+}
+
+/// Rounds in which every advice can run, as (earliest, latest). Round r can read challenges derived after groups
+/// before r, and has to complete group r. Latest is None if the advice does not contribute to any commitment group.
+/// Panics on cycles.
+pub fn round_bounds<S: Storage>(graph: &RTGraph<S>) -> Vec<(usize, Option<usize>)> {
+    let advices = graph.advices();
+    let n = advices.len();
+    let challenges: HashMap<S::RawAddr, usize> = graph.challenges().iter().map(|(after, addr)| (*addr, *after)).collect();
+    let committed: HashMap<S::RawAddr, usize> = graph.groups().iter().enumerate()
+        .flat_map(|(group, addrs)| addrs.iter().map(move |addr| (*addr, group)))
+        .collect();
+    let writers: HashMap<S::RawAddr, usize> = advices.iter().enumerate()
+        .flat_map(|(i, node)| node.advice.outputs().into_iter().map(move |addr| (addr, i)))
+        .collect();
+
+    let mut bounds: Vec<(usize, Option<usize>)> = vec![(0, None); n];
+    let mut readers = vec![vec![]; n];
+    let mut pending = vec![0; n];
+    for (j, node) in advices.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for addr in node.advice.inputs() {
+            if let Some(after) = challenges.get(&addr) {
+                bounds[j].0 = bounds[j].0.max(after + 1);
+            }
+            if let Some(&i) = writers.get(&addr) {
+                if seen.insert(i) {
+                    readers[i].push(j);
+                    pending[j] += 1;
+                }
+            }
+        }
+        bounds[j].1 = node.advice.outputs().iter().filter_map(|addr| committed.get(addr).copied()).min();
+    }
+
+    let mut order = Vec::with_capacity(n);
+    let mut ready: Vec<usize> = (0..n).filter(|i| pending[*i] == 0).collect();
+    while let Some(i) = ready.pop() {
+        order.push(i);
+        let earliest = bounds[i].0;
+        for &j in readers[i].iter() {
+            bounds[j].0 = bounds[j].0.max(earliest);
+            pending[j] -= 1;
+            if pending[j] == 0 {
+                ready.push(j);
+            }
+        }
+    }
+    assert!(order.len() == n, "advices depend on each other cyclically");
+
+    for &i in order.iter().rev() {
+        for &j in readers[i].iter() {
+            if let Some(latest) = bounds[j].1 {
+                bounds[i].1 = Some(bounds[i].1.unwrap_or(latest).min(latest));
+            }
+        }
+    }
+    bounds
+}
+
+/// Assigns every advice to the earliest round it can run in (see round_bounds) and stages the graph: advices are
+/// reordered by round, keeping their relative order within a round, and ends of rounds are recorded in
+/// RTGraph::stages. There is a round for every commitment group, and one more if challenges are derived after the
+/// last group. Returns all advices which can not be assigned a round, leaving the graph unchanged in this case.
+pub fn partition_rounds<S: Storage>(graph: &mut RTGraph<S>) -> Result<RoundReport, Vec<RoundError>> {
+    let bounds = round_bounds(graph);
+    let errors: Vec<RoundError> = bounds.iter().enumerate()
+        .filter_map(|(i, &(earliest, latest))| {
+            let latest = latest?;
+            (earliest > latest).then(|| RoundError { advice: i, namespace: graph.advices()[i].namespace.clone(), earliest, latest })
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let after_last_challenge = graph.challenges().iter().map(|(after, _)| after + 2).max().unwrap_or(1);
+    let rounds = graph.groups().len().max(after_last_challenge);
+    let mut stages: Vec<Vec<RTNode<S>>> = (0..rounds).map(|_| vec![]).collect();
+    for (node, (earliest, _)) in std::mem::take(graph.advices_mut()).into_iter().zip(bounds) {
+        stages[earliest].push(node);
+    }
+
+    let mut report = RoundReport::default();
+    let mut ends = vec![];
+    for stage in stages {
+        report.sizes.push(stage.len());
+        graph.advices_mut().extend(stage);
+        ends.push(graph.advices().len());
+    }
+    graph._set_stages(ends);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        backend::storage::{ReaderOf, WriterOf},
+        circuit::{BuildMode, ChallengeDependencyFlag, Challenges, Inputs, Signals, ToRawAddr},
+        middleend::compiler::compile,
+        test_utils::{square, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_partition_rounds() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let ch = c.alloc_challenge::<Fr>(0);
+        let ch: TSig = c.sig_from_raw_addr(ch.to_raw_addr());
+        let b = square(&mut c, a);
+        let z: TSig = c.alloc_sig();
+        c.group = 1;
+        let e = c.advise_sigs(&[b, ch], 1, |x| vec![x[0] * x[1]])[0];
+        let f = c.advise_sigs(&[a], 1, |x| vec![x[0].double()])[0];
+
+        let mut s = TestStorage::default();
+        let (mut graph, mapping) = compile(&c, &mut s);
+        assert_eq!(partition_rounds(&mut graph), Ok(RoundReport { sizes: vec![2, 1] }));
+        assert_eq!(graph.stages(), &vec![2, 3]);
+
+        s.put(&mapping[&a.to_raw_addr()], Fr::from(3));
+        for node in graph.stage(0) {
+            node.advice.call(&mut s);
+        }
+        assert_eq!(s.get(&mapping[&b.to_raw_addr()]), &Fr::from(9));
+        assert_eq!(s.get(&mapping[&f.to_raw_addr()]), &Fr::from(6));
+        s.put(&mapping[&ch.to_raw_addr()], Fr::from(5));
+        for node in graph.stage(1) {
+            node.advice.call(&mut s);
+        }
+        assert_eq!(s.get(&mapping[&e.to_raw_addr()]), &Fr::from(45));
+
+        // z is committed in group 0, but computed from the challenge derived after it, through an uncommitted value.
+        // Building such a circuit panics (see Challenges::_check_advice), so the dependency of y is unset.
+        let y = c.advise_dependent(&[ch], 1, |x| x)[0];
+        c._set_challenge_dependency(y.to_raw_addr(), None);
+        c.advise_into(&[y], &[z], |x| x);
+        let (mut graph, _) = compile(&c, &mut TestStorage::default());
+        let errors = partition_rounds(&mut graph).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].advice, errors[0].earliest, errors[0].latest), (3, 1, 0));
+        assert_eq!((errors[1].advice, errors[1].earliest, errors[1].latest), (4, 1, 0));
+        assert_eq!(graph.stages(), &vec![]);
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug, Display, Write},
+    str::FromStr,
+};
+
+use ff::PrimeField;
+
+use crate::backend::{
+    api::{RTAdvice, RTGraph, RTNode},
+    registry::{AdviceRegistry, KindAdvice, RestoreError},
+    storage::{RawAllocator, ReaderOf, Storage, TypedStorage, WriterOf},
+};
+
+use super::{
+    escape::{escape, unescape},
+    fusion::FusedAdvice,
+    validate::AdviceRef,
+};
+
+fn join<A: Display>(addrs: &[A]) -> String {
+    addrs.iter().map(|addr| format!(" {addr}")).collect()
+}
+
+/// Why a graph can not be saved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SaveError<A> {
+    /// Advice built from an anonymous closure, see RTAdvice::kinds.
+    Anonymous { advice: usize, namespace: String },
+    /// Value which is not a field element, while all advice kinds are over the field.
+    NotField { addr: A },
+    /// Parameter of an advice kind which is empty, or contains whitespace, commas or parentheses (see
+    /// AdviceKind::params), so it can not be read back.
+    Param { advice: usize, namespace: String, kind: &'static str, param: String },
+}
+
+impl<A: Debug> Display for SaveError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Anonymous { advice, namespace } =>
+                write!(f, "{} is built from an anonymous closure", AdviceRef(*advice, namespace)),
+            SaveError::NotField { addr } => write!(f, "value {addr:?} is not a field element"),
+            SaveError::Param { advice, namespace, kind, param } =>
+                write!(f, "{} calls {kind} with parameter {param:?}", AdviceRef(*advice, namespace)),
+        }
+    }
+}
+
+/// Checks that the parameter is read back as it is written: it is not empty, and contains no whitespace, commas or
+/// parentheses.
+fn is_valid_param(param: &str) -> bool {
+    !param.is_empty() && !param.contains(|ch: char| ch.is_whitespace() || ",()".contains(ch))
+}
+
+/// Saves the execution graph in a line-based text format. Advices are saved as calls of registered advice kinds
+/// (see RTAdvice::kinds), so the graph can be restored by load_graph without building the circuit. Values are not
+/// saved with their types, so all of them must be field elements, as the types in storage s tell.
+/// Returns an error for the first advice built from an anonymous closure or with a parameter which can not be saved,
+/// or for the first value of another type.
+pub fn save_graph<S, F>(graph: &RTGraph<S>, s: &S) -> Result<String, SaveError<S::RawAddr>>
+where
+    S: TypedStorage,
+    S::RawAddr: Display,
+    F: PrimeField,
+{
+    let mut advices = vec![];
+    for (i, node) in graph.advices().iter().enumerate() {
+        let calls = node.advice.kinds().ok_or_else(|| SaveError::Anonymous { advice: i, namespace: node.namespace.clone() })?;
+        for call in calls.iter() {
+            if let Some(param) = call.params.iter().find(|param| !is_valid_param(param)) {
+                let namespace = node.namespace.clone();
+                return Err(SaveError::Param { advice: i, namespace, kind: call.name, param: param.clone() });
+            }
+        }
+        advices.push((node.namespace.as_str(), calls));
+    }
+
+    let mut seen = HashSet::new();
+    let values: Vec<S::RawAddr> = graph.inputs().iter()
+        .chain(graph.constants().iter())
+        .chain(graph.outputs().iter())
+        .chain(graph.groups().iter().flatten())
+        .chain(graph.challenges().iter().map(|(_, addr)| addr))
+        .copied()
+        .chain(advices.iter().flat_map(|(_, calls)| calls.iter().flat_map(|call| call.inputs.iter().chain(call.outputs.iter()).copied())))
+        .filter(|addr| seen.insert(*addr))
+        .collect();
+    if let Some(addr) = values.iter().find(|addr| s.type_of(addr) != TypeId::of::<F>()) {
+        return Err(SaveError::NotField { addr: *addr });
+    }
+
+    let mut out = String::new();
+    writeln!(out, "values{}", join(&values)).unwrap();
+    writeln!(out, "inputs{}", join(graph.inputs())).unwrap();
+    writeln!(out, "constants{}", join(graph.constants())).unwrap();
+    writeln!(out, "outputs{}", join(graph.outputs())).unwrap();
+    for group in graph.groups() {
+        writeln!(out, "group{}", join(group)).unwrap();
+    }
+    for (after, addr) in graph.challenges() {
+        writeln!(out, "challenge {after} {addr}").unwrap();
+    }
+    writeln!(out, "stages{}", join(graph.stages())).unwrap();
+    for (namespace, calls) in advices {
+        writeln!(out, "advice {}", escape(namespace)).unwrap();
+        for call in calls {
+            writeln!(out, "call {}({}){} ->{}", call.name, call.params.join(","), join(&call.inputs), join(&call.outputs)).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+/// Why a saved graph can not be loaded. Lines are numbered from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// Line which is not in the format written by save_graph.
+    Malformed { line: usize, text: String },
+    /// Address which is not among values.
+    UnknownValue { line: usize, addr: String },
+    /// Address listed among values twice.
+    DuplicateValue { line: usize, addr: String },
+    /// Call of an advice kind which can not be restored.
+    Kind { line: usize, error: RestoreError },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Malformed { line, text } => write!(f, "line {line}: malformed line {text:?}"),
+            LoadError::UnknownValue { line, addr } => write!(f, "line {line}: address {addr} is not among values"),
+            LoadError::DuplicateValue { line, addr } => write!(f, "line {line}: value {addr} is listed twice"),
+            LoadError::Kind { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+
+/// Namespace and calls of a restored advice.
+type LoadedNode<S> = (String, Vec<Box<dyn RTAdvice<S>>>);
+
+/// Restored graph and the mapping from saved addresses to allocated ones.
+type LoadedGraph<S> = (RTGraph<S>, HashMap<<S as Storage>::RawAddr, <S as Storage>::RawAddr>);
+
+/// Restores the graph saved by save_graph, allocating its values in storage s as field elements, and returns it
+/// alongside the mapping from saved addresses to allocated ones. Advices of several calls are restored as
+/// FusedAdvice. Returns an error if the text is malformed or uses advice kinds which can not be restored; values
+/// allocated before the error stay in storage.
+pub fn load_graph<S, F>(text: &str, registry: &AdviceRegistry<F>, s: &mut S) -> Result<LoadedGraph<S>, LoadError>
+where
+    S: RawAllocator + ReaderOf<F> + WriterOf<F> + 'static,
+    S::RawAddr: FromStr,
+    F: PrimeField,
+{
+    let mut mapping: HashMap<S::RawAddr, S::RawAddr> = HashMap::new();
+
+    let mut inputs = vec![];
+    let mut constants = vec![];
+    let mut outputs = vec![];
+    let mut groups = vec![];
+    let mut challenges = vec![];
+    let mut stages = vec![];
+    let mut advices: Vec<LoadedNode<S>> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let malformed = || LoadError::Malformed { line: number, text: line.to_string() };
+        let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if tag == "advice" {
+            advices.push((unescape(rest).ok_or_else(malformed)?, vec![]));
+            continue;
+        }
+        let parse = |token: &str| token.parse::<S::RawAddr>().map_err(|_| malformed());
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        if tag == "values" {
+            for token in tokens {
+                let addr = s.allocate_raw(TypeId::of::<F>());
+                if mapping.insert(parse(token)?, addr).is_some() {
+                    return Err(LoadError::DuplicateValue { line: number, addr: token.to_string() });
+                }
+            }
+            continue;
+        }
+        let map = |token: &str| -> Result<S::RawAddr, LoadError> {
+            mapping.get(&parse(token)?).copied().ok_or_else(|| LoadError::UnknownValue { line: number, addr: token.to_string() })
+        };
+        let map_all = |tokens: &[&str]| tokens.iter().map(|token| map(token)).collect::<Result<Vec<_>, _>>();
+        let number_of = |token: &str| token.parse::<usize>().map_err(|_| malformed());
+        match tag {
+            "inputs" => inputs = map_all(&tokens)?,
+            "constants" => constants = map_all(&tokens)?,
+            "outputs" => outputs = map_all(&tokens)?,
+            "group" => groups.push(map_all(&tokens)?),
+            "challenge" => {
+                let [after, addr] = tokens[..] else { return Err(malformed()) };
+                challenges.push((number_of(after)?, map(addr)?));
+            }
+            "stages" => stages = tokens.into_iter().map(number_of).collect::<Result<_, _>>()?,
+            "call" => {
+                let (kind, io) = tokens.split_first().ok_or_else(malformed)?;
+                let (name, params) = kind.strip_suffix(')').and_then(|kind| kind.split_once('(')).ok_or_else(malformed)?;
+                let params: Vec<String> = params.split(',').filter(|param| !param.is_empty()).map(String::from).collect();
+                let arrow = io.iter().position(|token| *token == "->").ok_or_else(malformed)?;
+                let advice = KindAdvice::<S, F> {
+                    kind: registry.restore(name, &params).map_err(|error| LoadError::Kind { line: number, error })?,
+                    inputs: map_all(&io[..arrow])?,
+                    outputs: map_all(&io[arrow + 1..])?,
+                };
+                advices.last_mut().ok_or_else(malformed)?.1.push(Box::new(advice));
+            }
+            _ => return Err(malformed()),
+        }
+    }
+
+    // Values passed inside fused advices are kept there again, unless the backend or other advices read them.
+    let mut readers: HashMap<S::RawAddr, Vec<usize>> = HashMap::new();
+    for (i, (_, calls)) in advices.iter().enumerate() {
+        for addr in calls.iter().flat_map(|call| call.inputs()) {
+            readers.entry(addr).or_default().push(i);
+        }
+    }
+    let read_by_backend: HashSet<S::RawAddr> = outputs.iter().chain(groups.iter().flatten()).copied().collect();
+    let advices = advices.into_iter()
+        .enumerate()
+        .map(|(i, (namespace, mut calls))| {
+            let advice = match calls.len() {
+                1 => calls.pop().unwrap(),
+                _ => {
+                    let exposed = calls.iter()
+                        .flat_map(|call| call.outputs())
+                        .filter(|addr| read_by_backend.contains(addr) || readers.get(addr).into_iter().flatten().any(|j| *j != i))
+                        .collect();
+                    Box::new(FusedAdvice::new(calls, &exposed))
+                }
+            };
+            RTNode { advice, namespace }
+        })
+        .collect();
+    let mut graph = RTGraph::new(inputs, constants, outputs, advices, groups, challenges);
+    graph._set_stages(stages);
+    Ok((graph, mapping))
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::{Field, PrimeField};
+    use halo2curves::bn256::{Fq, Fr};
+    use num_bigint::BigUint;
+
+    use crate::{
+        backend::{
+            registry::{AdviceKind, AdviceRegistry, Inverse, KindAdvices, Lc, Pow5, SplitLimbs},
+            storage::{ReaderOf, WriterOf},
+        },
+        circuit::{BuildMode, Inputs, Namespaces, ToRawAddr},
+        middleend::{compiler::compile, fusion::fuse_advices},
+        test_utils::{execute, TSig, TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_save_graph() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.in_namespace("hash", |c| c.advise_kind(Pow5, &[a], 1)[0]);
+        let limbs = c.advise_kind(SplitLimbs { base: 16u32.into(), num_limbs: 2 }, &[b], 2);
+        let d = c.advise_kind(Lc { coeffs: vec![Fr::from(2), -Fr::one()] }, &limbs, 1)[0];
+        let e = c.advise_kind(Inverse, &[d], 1)[0];
+
+        let mut s = TestStorage::default();
+        let (mut graph, mapping) = compile(&c, &mut s);
+        let text = save_graph::<_, Fr>(&graph, &s).unwrap();
+        assert!(text.contains(&format!("advice hash\ncall pow5() {} -> {}\n", mapping[&a.to_raw_addr()], mapping[&b.to_raw_addr()])));
+        assert!(text.contains("call split_limbs(16,2) "));
+        let minus_one = BigUint::from_bytes_le((-Fr::one()).to_repr().as_ref());
+        assert!(text.contains(&format!("call lc(2,{minus_one}) ")));
+        assert_eq!(save_graph::<_, Fq>(&graph, &s), Err(SaveError::NotField { addr: mapping[&a.to_raw_addr()] }));
+
+        fuse_advices(&mut graph);
+        let text = save_graph::<_, Fr>(&graph, &s).unwrap();
+        let mut s = TestStorage::default();
+        let (loaded, remap) = load_graph(&text, &AdviceRegistry::new(), &mut s).unwrap();
+        assert_eq!(loaded.advices().len(), 1);
+        assert_eq!(save_graph::<_, Fr>(&loaded, &s).map(|text| text.lines().count()), Ok(text.lines().count()));
+
+        let addr = |sig: TSig| remap[&mapping[&sig.to_raw_addr()]];
+        s.put(&addr(a), Fr::from(3));
+        execute(&loaded, &mut s);
+        assert_eq!(s.get(&addr(limbs[1])), &Fr::from(15));
+        assert_eq!(s.get(&addr(e)), &(-Fr::from(9)).invert().unwrap());
+
+        c.advise_sigs(&[e], 1, |x| x);
+        let mut s = TestStorage::default();
+        let (graph, _) = compile(&c, &mut s);
+        assert_eq!(save_graph::<_, Fr>(&graph, &s), Err(SaveError::Anonymous { advice: 4, namespace: "".to_string() }));
+    }
+
+    /// Identity, tagged by an arbitrary parameter.
+    struct Tagged(&'static str);
+
+    impl AdviceKind<Fr> for Tagged {
+        fn name(&self) -> &'static str {
+            "tagged"
+        }
+
+        fn params(&self) -> Vec<String> {
+            vec![self.0.to_string()]
+        }
+
+        fn call(&self, inputs: &[Fr]) -> Vec<Fr> {
+            inputs.to_vec()
+        }
+    }
+
+    #[test]
+    fn test_save_graph_params() {
+        for tag in ["a,b", "a)", "a b", ""] {
+            let mut c = TestCircuit::new(BuildMode::Full);
+            let a = c.alloc_input::<Fr>();
+            c.in_namespace("tag", |c| c.advise_kind(Tagged(tag), &[a], 1));
+
+            let mut s = TestStorage::default();
+            let (graph, _) = compile(&c, &mut s);
+            let namespace = "tag".to_string();
+            let error = SaveError::Param { advice: 0, namespace, kind: "tagged", param: tag.to_string() };
+            assert_eq!(save_graph::<_, Fr>(&graph, &s), Err(error));
+        }
+    }
+
+    #[test]
+    fn test_save_graph_namespaces() {
+        let namespace = " a\\b\r\n\tc\u{7f}\u{e9} ";
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        c.in_namespace(namespace, |c| c.advise_kind(Pow5, &[a], 1));
+
+        let mut s = TestStorage::default();
+        let (graph, _) = compile(&c, &mut s);
+        let text = save_graph::<_, Fr>(&graph, &s).unwrap();
+        assert!(text.contains("advice  a\\\\b\\r\\n\\tc\\u007f\u{e9} \n"));
+        let (loaded, _) = load_graph(&text, &AdviceRegistry::<Fr>::new(), &mut TestStorage::default()).unwrap();
+        assert_eq!(loaded.advices()[0].namespace, namespace);
+    }
+
+    #[test]
+    fn test_load_graph_errors() {
+        let load = |text: &str| load_graph(text, &AdviceRegistry::<Fr>::new(), &mut TestStorage::default()).err();
+        let malformed = |line: usize, text: &str| Some(LoadError::Malformed { line, text: text.to_string() });
+
+        assert_eq!(load("values 0 1\nvertices 0"), malformed(2, "vertices 0"));
+        assert_eq!(load("values 0 x"), malformed(1, "values 0 x"));
+        assert_eq!(load("values 0\ncall pow5() 0 -> 0"), malformed(2, "call pow5() 0 -> 0"));
+        assert_eq!(load("values 0\nadvice\ncall pow5 0 -> 0"), malformed(3, "call pow5 0 -> 0"));
+        assert_eq!(load("advice a\\qb"), malformed(1, "advice a\\qb"));
+        assert_eq!(load("challenge 0"), malformed(1, "challenge 0"));
+        assert_eq!(load("values 0 0"), Some(LoadError::DuplicateValue { line: 1, addr: "0".to_string() }));
+        assert_eq!(load("values 0\ninputs 0 1"), Some(LoadError::UnknownValue { line: 2, addr: "1".to_string() }));
+        assert_eq!(
+            load("values 0\nadvice\ncall square() 0 -> 0"),
+            Some(LoadError::Kind { line: 3, error: RestoreError::Unregistered("square".to_string()) }),
+        );
+        assert!(matches!(load("values 0\nadvice\ncall lc(x) 0 -> 0"), Some(LoadError::Kind { line: 3, error: RestoreError::Params { .. } })));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::{self, Display}, ops::AddAssign};
+
+use crate::circuit::{AdviceLog, CommitmentGroups, ConstantFlag, ConstraintLog, Lookups, PrimarySignalFlag};
+
+use super::{bounds::range_size, escape::escape};
+
+/// Sizes of a part of the circuit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// All values, including signals.
+    pub vars: usize,
+    /// Committed signals.
+    pub primary: usize,
+    /// Signals which are not committed, including challenges.
+    pub dependent: usize,
+    pub constants: usize,
+    /// Amount of constraints of each degree.
+    pub constraints: Vec<usize>,
+    pub lookups: usize,
+    /// Lookups into range check tables (see range_table). This is not the amount of range checks: a single check may
+    /// look up several limbs, or a shifted value (see LookupRangecheck), and checks by other means are not counted.
+    pub range_table_lookups: usize,
+    pub advices: usize,
+}
+
+impl AddAssign<&Counts> for Counts {
+    fn add_assign(&mut self, other: &Counts) {
+        self.vars += other.vars;
+        self.primary += other.primary;
+        self.dependent += other.dependent;
+        self.constants += other.constants;
+        if self.constraints.len() < other.constraints.len() {
+            self.constraints.resize(other.constraints.len(), 0);
+        }
+        for (degree, amount) in other.constraints.iter().enumerate() {
+            self.constraints[degree] += amount;
+        }
+        self.lookups += other.lookups;
+        self.range_table_lookups += other.range_table_lookups;
+        self.advices += other.advices;
+    }
+}
+
+impl Counts {
+    fn add_constraint(&mut self, degree: usize) {
+        if self.constraints.len() <= degree {
+            self.constraints.resize(degree + 1, 0);
+        }
+        self.constraints[degree] += 1;
+    }
+
+    fn to_json(&self) -> String {
+        let constraints: Vec<String> = self.constraints.iter().map(|amount| amount.to_string()).collect();
+        format!(
+            "{{\"vars\":{},\"primary\":{},\"dependent\":{},\"constants\":{},\"constraints\":[{}],\"lookups\":{},\"range_table_lookups\":{},\"advices\":{}}}",
+            self.vars, self.primary, self.dependent, self.constants, constraints.join(","), self.lookups,
+            self.range_table_lookups, self.advices,
+        )
+    }
+}
+
+/// Sizes of the circuit, in total and by namespace. Counts of a namespace do not include nested namespaces.
+/// Values are attributed to the namespace of the advice writing them, and constraints and lookups to the namespace
+/// they were recorded in. Values not written by any advice (e.g. inputs) belong to the root namespace "".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Statistics {
+    pub total: Counts,
+    /// Amount of commitment groups, up to the last one containing some values.
+    pub groups: usize,
+    pub namespaces: BTreeMap<String, Counts>,
+}
+
+impl Statistics {
+    pub fn to_json(&self) -> String {
+        let namespaces: Vec<String> = self.namespaces.iter()
+            .map(|(namespace, counts)| format!("\"{}\":{}", escape(namespace), counts.to_json()))
+            .collect();
+        format!("{{\"total\":{},\"groups\":{},\"namespaces\":{{{}}}}}", self.total.to_json(), self.groups, namespaces.join(","))
+    }
+}
+
+/// Human-readable table, with a row per namespace and a row of totals.
+impl Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let degrees = self.total.constraints.len();
+        let mut header = vec!["namespace".to_string()];
+        header.extend(["vars", "primary", "dependent", "constants"].map(String::from));
+        header.extend((0..degrees).map(|degree| format!("deg {degree}")));
+        header.extend(["lookups", "range table lookups", "advices"].map(String::from));
+
+        let row = |name: &str, counts: &Counts| {
+            let mut row = vec![name.to_string()];
+            row.extend([counts.vars, counts.primary, counts.dependent, counts.constants].map(|x| x.to_string()));
+            row.extend((0..degrees).map(|degree| counts.constraints.get(degree).copied().unwrap_or(0).to_string()));
+            row.extend([counts.lookups, counts.range_table_lookups, counts.advices].map(|x| x.to_string()));
+            row
+        };
+        let mut rows = vec![header];
+        for (namespace, counts) in self.namespaces.iter() {
+            rows.push(row(if namespace.is_empty() { "(root)" } else { namespace }, counts));
+        }
+        rows.push(row("total", &self.total));
+
+        let widths: Vec<usize> = (0..rows[0].len()).map(|i| rows.iter().map(|row| row[i].len()).max().unwrap()).collect();
+        for row in rows.iter() {
+            let cells: Vec<String> = row.iter().zip(widths.iter()).enumerate()
+                .map(|(i, (cell, width))| if i == 0 { format!("{cell:<width$}") } else { format!("{cell:>width$}") })
+                .collect();
+            writeln!(f, "{}", cells.join("  "))?;
+        }
+        writeln!(f, "commitment groups: {}", self.groups)
+    }
+}
+
+pub trait CircuitStatistics: AdviceLog + ConstraintLog + Lookups + PrimarySignalFlag + ConstantFlag + CommitmentGroups {
+    fn stats(&self) -> Statistics {
+        let mut namespaces: BTreeMap<String, Counts> = BTreeMap::new();
+        let mut writers: HashMap<Self::RawAddr, usize> = HashMap::new();
+        for (i, record) in self.advice_log().iter().enumerate() {
+            namespaces.entry(record.namespace.clone()).or_default().advices += 1;
+            for addr in record.outputs.iter() {
+                writers.entry(*addr).or_insert(i);
+            }
+        }
+        let namespace_of = |addr| writers.get(&addr).map_or(String::new(), |i| self.advice_log()[*i].namespace.clone());
+
+        let mut groups = 0;
+        for addr in self.raw_addrs() {
+            if !self.is_var(addr) {
+                continue;
+            }
+            let counts = namespaces.entry(namespace_of(addr)).or_default();
+            counts.vars += 1;
+            if self.is_sig(addr) && self.is_primary(addr) {
+                counts.primary += 1;
+            } else if self.is_sig(addr) {
+                counts.dependent += 1;
+            }
+            if self.is_const(addr) {
+                counts.constants += 1;
+            }
+            if let Some(group) = self.group(addr) {
+                groups = groups.max(group + 1);
+            }
+        }
+        for constraint in self.constraints().iter() {
+            namespaces.entry(constraint.namespace.clone()).or_default().add_constraint(constraint.degree());
+        }
+        let ranges: Vec<bool> = self.tables().iter().map(|table| range_size(table).is_some()).collect();
+        for lookup in self.lookups().iter() {
+            let counts = namespaces.entry(lookup.namespace.clone()).or_default();
+            counts.lookups += 1;
+            if ranges[lookup.table.0] {
+                counts.range_table_lookups += 1;
+            }
+        }
+
+        let mut total = Counts::default();
+        for counts in namespaces.values() {
+            total += counts;
+        }
+        Statistics { total, groups, namespaces }
+    }
+}
+
+impl<C> CircuitStatistics for C
+where
+    C: AdviceLog + ConstraintLog + Lookups + PrimarySignalFlag + ConstantFlag + CommitmentGroups,
+{}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, Lookups, Namespaces, _Into},
+        gadgets::traits::bigint_arith::range_table,
+        test_utils::{square, TestCircuit},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        c.constrain(Constraint::new().term(Fr::one(), &[a._into()]).term(-Fr::from(3), &[]));
+        let range = range_table(&mut c, 16);
+        let b = c.in_namespace("gadget", |c| {
+            let b = square(c, a);
+            c.lookup(range, &[b]);
+            c.in_namespace("inner", |c| c.advise_dependent(&[b], 1, |x| x));
+            b
+        });
+        c.advise_dependent(&[b], 1, |x| x);
+
+        let stats = c.stats();
+        assert_eq!(stats.namespaces.len(), 3);
+        assert_eq!(stats.to_json(), concat!(
+            r#"{"total":{"vars":4,"primary":2,"dependent":2,"constants":0,"constraints":[0,1,1],"lookups":1,"range_table_lookups":1,"advices":3},"#,
+            r#""groups":1,"namespaces":{"#,
+            r#""":{"vars":2,"primary":1,"dependent":1,"constants":0,"constraints":[0,1],"lookups":0,"range_table_lookups":0,"advices":1},"#,
+            r#""gadget":{"vars":1,"primary":1,"dependent":0,"constants":0,"constraints":[0,0,1],"lookups":1,"range_table_lookups":1,"advices":1},"#,
+            r#""gadget/inner":{"vars":1,"primary":0,"dependent":1,"constants":0,"constraints":[],"lookups":0,"range_table_lookups":0,"advices":1}}}"#,
+        ));
+
+        let table = stats.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("namespace     vars  primary  dependent  constants  deg 0  deg 1  deg 2  lookups"));
+        assert!(lines[1].starts_with("(root)  "));
+        assert_eq!(lines[4].split_whitespace().collect::<Vec<_>>(), ["total", "4", "2", "2", "0", "0", "1", "1", "1", "1", "3"]);
+        assert_eq!(lines[5], "commitment groups: 1");
+    }
+
+    #[test]
+    fn test_stats_recorded_namespaces() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let range = range_table(&mut c, 16);
+        let b = c.in_namespace("gadget", |c| c.advise_sigs(&[a], 1, |x| x)[0]);
+        // Constraints on values written in another namespace belong to the namespace they are recorded in.
+        c.in_namespace("check", |c| {
+            c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::one(), &[a._into()]));
+            c.lookup(range, &[b]);
+        });
+
+        let stats = c.stats();
+        let check = &stats.namespaces["check"];
+        assert_eq!((check.vars, check.advices, check.lookups, check.range_table_lookups), (0, 0, 1, 1));
+        assert_eq!(check.constraints, vec![0, 1]);
+        let gadget = &stats.namespaces["gadget"];
+        assert_eq!((gadget.vars, gadget.advices, gadget.lookups), (1, 1, 0));
+        assert!(gadget.constraints.is_empty());
+    }
+}
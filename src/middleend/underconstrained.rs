@@ -0,0 +1,203 @@
+use std::{collections::{HashMap, HashSet}, fmt::{self, Debug, Display}, panic::Location};
+
+use ff::Field;
+
+use crate::circuit::{AdviceLog, ChallengeFlag, ConstantFlag, Constraint, ConstraintLog, Lookups, PublicFlag};
+
+/// Why a signal is considered underconstrained.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freedom {
+    /// Signal appears in no constraint and no lookup.
+    Unconstrained,
+    /// Signal appears only in lookups, which restrict it to a set of values but do not determine it.
+    OnlyLookups,
+    /// Signal appears only in non-linear terms, e.g. x * y = z leaves x free if y = 0.
+    OnlyNonlinear,
+    /// Signal appears in linear terms, but only of constraints with other undetermined signals, e.g. x + y = 0.
+    Underdetermined,
+}
+
+/// Signal written by an advice which is not determined by the constraints.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Underconstrained<A> {
+    pub addr: A,
+    pub freedom: Freedom,
+    /// Index of the advice writing the signal, in AdviceLog::advice_log.
+    pub advice: usize,
+    pub namespace: String,
+    pub location: &'static Location<'static>,
+}
+
+impl<A: Debug> Display for Underconstrained<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let freedom = match self.freedom {
+            Freedom::Unconstrained => "is not constrained",
+            Freedom::OnlyLookups => "is only constrained by lookups",
+            Freedom::OnlyNonlinear => "only occurs in non-linear terms",
+            Freedom::Underdetermined => "only occurs in constraints with other undetermined signals",
+        };
+        write!(f, "{}: ", self.location)?;
+        if !self.namespace.is_empty() {
+            write!(f, "{}: ", self.namespace)?;
+        }
+        write!(f, "signal {:?} written by advice {} {freedom}", self.addr, self.advice)
+    }
+}
+
+/// Signals which a constraint can be solved for: those occurring in it exactly once, in a linear term with nonzero
+/// coefficient.
+fn solvable<C: ConstraintLog>(constraint: &Constraint<C>) -> HashSet<C::RawAddr> {
+    let mut counts: HashMap<C::RawAddr, usize> = HashMap::new();
+    for (_, sigs) in constraint.terms.iter() {
+        for sig in sigs.iter() {
+            *counts.entry(*sig).or_default() += 1;
+        }
+    }
+    constraint.terms.iter()
+        .filter_map(|(coeff, sigs)| match sigs[..] {
+            [sig] if counts[&sig] == 1 && !bool::from(coeff.is_zero()) => Some(sig),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds signals (primary or dependent) written by advices which are not determined by the constraints. Signals not
+/// written by advices, constants, challenges and public outputs are known; a signal is determined if some constraint
+/// can be solved for it (it occurs there exactly once, in a linear term with nonzero coefficient) and all other
+/// signals of the constraint are known or determined. Determination is propagated until a fixpoint, so every
+/// determined signal has a unique value given the known ones. Non-linear constraints are only used when solved for a
+/// linear term, so signals fixed by them (e.g. x * x = 0) are reported.
+/// Must be run before linear elimination (see eliminate_linear), which removes the constraints determining
+/// eliminated signals.
+pub fn find_underconstrained<C>(c: &C) -> Vec<Underconstrained<C::RawAddr>>
+where
+    C: AdviceLog + ConstraintLog + Lookups + ChallengeFlag + ConstantFlag + PublicFlag,
+{
+    let mut unknown: HashSet<C::RawAddr> = c.advice_log().iter()
+        .flat_map(|record| record.outputs.iter().copied())
+        .filter(|addr| c.is_sig(*addr) && !c.is_const(*addr) && c.challenge_after(*addr).is_none() && !c.is_public(*addr))
+        .collect();
+    let solvable: Vec<HashSet<C::RawAddr>> = c.constraints().iter().map(solvable).collect();
+    loop {
+        let mut changed = false;
+        for (constraint, solvable) in c.constraints().iter().zip(solvable.iter()) {
+            let free: HashSet<C::RawAddr> = constraint.terms.iter()
+                .flat_map(|(_, sigs)| sigs.iter().copied())
+                .filter(|sig| unknown.contains(sig))
+                .collect();
+            if let Some(&sig) = free.iter().next() {
+                if free.len() == 1 && solvable.contains(&sig) {
+                    unknown.remove(&sig);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let occurs: HashSet<C::RawAddr> = c.constraints().iter()
+        .flat_map(|constraint| constraint.terms.iter().flat_map(|(_, sigs)| sigs.iter().copied()))
+        .collect();
+    let linear: HashSet<C::RawAddr> = solvable.into_iter().flatten().collect();
+    let looked_up: HashSet<C::RawAddr> = c.lookups().iter().flat_map(|lookup| lookup.sigs.iter().copied()).collect();
+
+    let mut report = vec![];
+    for (i, record) in c.advice_log().iter().enumerate() {
+        for addr in record.outputs.iter() {
+            if !c.is_sig(*addr) || c.is_const(*addr) || c.challenge_after(*addr).is_some() || c.is_public(*addr) {
+                continue;
+            }
+            let freedom = if !unknown.contains(addr) {
+                continue;
+            } else if linear.contains(addr) {
+                Freedom::Underdetermined
+            } else if occurs.contains(addr) {
+                Freedom::OnlyNonlinear
+            } else if looked_up.contains(addr) {
+                Freedom::OnlyLookups
+            } else {
+                Freedom::Unconstrained
+            };
+            report.push(Underconstrained {
+                addr: *addr,
+                freedom,
+                advice: i,
+                namespace: record.namespace.clone(),
+                location: record.location,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{BuildMode, Constraint, ConstraintLog, Inputs, Lookups, Namespaces, Publics, ToRawAddr, _Into},
+        gadgets::traits::bigint_arith::range_table,
+        test_utils::{square, TestCircuit},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_find_underconstrained() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = square(&mut c, a);
+        let d = c.advise_sigs(&[a], 1, |x| x)[0];
+        let e = c.advise_sigs(&[a], 1, |x| x)[0];
+        let table = range_table(&mut c, 16);
+        c.lookup(table, &[e]);
+        let g = c.in_namespace("mul", |c| c.advise_sigs(&[a, b], 1, |x| vec![x[1] * x[0].invert().unwrap()])[0]);
+        c.constrain(Constraint::new().term(Fr::one(), &[a._into(), g._into()]).term(-Fr::one(), &[b._into()]));
+
+        let report = find_underconstrained(&c);
+        let found: Vec<_> = report.iter().map(|u| (u.addr, u.freedom)).collect();
+        assert_eq!(found, vec![
+            (d.to_raw_addr(), Freedom::Unconstrained),
+            (e.to_raw_addr(), Freedom::OnlyLookups),
+            (g.to_raw_addr(), Freedom::OnlyNonlinear),
+        ]);
+        assert_eq!(report[2].namespace, "mul");
+        assert!(report[2].location.file().ends_with("underconstrained.rs"));
+    }
+
+    #[test]
+    fn test_find_underconstrained_underdetermined() {
+        // x + y = 0 has a solution for any x, and z = y inherits the freedom.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let [x, y, z] = [(); 3].map(|_| c.advise_sigs(&[a], 1, |x| x)[0]);
+        c.constrain(Constraint::new().term(Fr::one(), &[z._into()]).term(-Fr::one(), &[y._into()]));
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into()]).term(Fr::one(), &[y._into()]));
+
+        let found: Vec<_> = find_underconstrained(&c).iter().map(|u| (u.addr, u.freedom)).collect();
+        assert_eq!(found, [x, y, z].map(|sig| (sig.to_raw_addr(), Freedom::Underdetermined)));
+
+        // Fixing x determines y and then z, although the constraints come in the opposite order.
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into()]).term(-Fr::one(), &[a._into()]).term(Fr::one(), &[]));
+        assert!(find_underconstrained(&c).is_empty());
+    }
+
+    #[test]
+    fn test_find_underconstrained_known() {
+        // Public outputs are checked by the verifier, so they determine the rest; x * x = 0 is not solved.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let p = c.advise_sigs(&[a], 1, |x| x)[0];
+        let b = c.advise_sigs(&[a], 1, |x| x)[0];
+        let x = c.advise_sigs(&[a], 1, |_| vec![Fr::ZERO])[0];
+        c.make_public(p);
+        c.constrain(Constraint::new().term(Fr::one(), &[p._into()]).term(-Fr::from(2), &[b._into()]));
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into(), x._into()]));
+
+        let found: Vec<_> = find_underconstrained(&c).iter().map(|u| (u.addr, u.freedom)).collect();
+        assert_eq!(found, vec![(x.to_raw_addr(), Freedom::OnlyNonlinear)]);
+    }
+}
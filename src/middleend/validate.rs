@@ -0,0 +1,336 @@
+use std::{collections::{HashMap, HashSet}, fmt::{self, Debug, Display}};
+
+use crate::{
+    backend::{api::RTGraph, storage::Storage},
+    circuit::{AdviceLog, AliasFlag, ConstraintLog, Lookups},
+};
+
+/// Problem found in an execution graph. Advices are referred to by their index in RTGraph::advices, alongside
+/// the namespace they were recorded in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphError<A> {
+    /// Advices depending on each other, in order of recording.
+    Cycle { advices: Vec<(usize, String)> },
+    /// Address written by an advice after it was already written by advice first.
+    MultipleWriters { addr: A, advice: usize, namespace: String, first: usize },
+    /// Address provided externally (input, constant or challenge), but also written by an advice.
+    WritesExternal { addr: A, advice: usize, namespace: String },
+    /// Address read by an advice, but neither written by any advice nor provided externally.
+    Unwritten { addr: A, advice: usize, namespace: String },
+    /// Output neither written by any advice nor provided externally.
+    Unproduced { addr: A },
+}
+
+pub(super) struct AdviceRef<'a>(pub(super) usize, pub(super) &'a str);
+
+impl Display for AdviceRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.1.is_empty() {
+            write!(f, "advice {}", self.0)
+        } else {
+            write!(f, "advice {} in {}", self.0, self.1)
+        }
+    }
+}
+
+impl<A: Debug> Display for GraphError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::Cycle { advices } => {
+                write!(f, "cycle between advices:")?;
+                for (advice, namespace) in advices {
+                    write!(f, " [{}]", AdviceRef(*advice, namespace))?;
+                }
+                Ok(())
+            }
+            GraphError::MultipleWriters { addr, advice, namespace, first } =>
+                write!(f, "{} writes {addr:?}, which is already written by advice {first}", AdviceRef(*advice, namespace)),
+            GraphError::WritesExternal { addr, advice, namespace } =>
+                write!(f, "{} writes {addr:?}, which is provided externally", AdviceRef(*advice, namespace)),
+            GraphError::Unwritten { addr, advice, namespace } =>
+                write!(f, "{} reads {addr:?}, which is never written", AdviceRef(*advice, namespace)),
+            GraphError::Unproduced { addr } =>
+                write!(f, "output {addr:?} is never written"),
+        }
+    }
+}
+
+/// Checks that the graph can be executed: every value is written exactly once, either by an advice or externally
+/// (inputs, constants and challenges), and advices do not depend on each other cyclically. Returns all problems found, empty if the graph is valid.
+pub fn validate<S: Storage>(graph: &RTGraph<S>) -> Vec<GraphError<S::RawAddr>> {
+    let mut errors = vec![];
+    let advices = graph.advices();
+
+    let external: HashSet<S::RawAddr> = graph.inputs().iter().copied()
+        .chain(graph.constants().iter().copied())
+        .chain(graph.challenges().iter().map(|(_, addr)| *addr))
+        .collect();
+
+    let mut writers: HashMap<S::RawAddr, usize> = HashMap::new();
+    for (i, node) in advices.iter().enumerate() {
+        for addr in node.advice.outputs() {
+            if external.contains(&addr) {
+                errors.push(GraphError::WritesExternal { addr, advice: i, namespace: node.namespace.clone() });
+            } else if let Some(&first) = writers.get(&addr) {
+                errors.push(GraphError::MultipleWriters { addr, advice: i, namespace: node.namespace.clone(), first });
+            } else {
+                writers.insert(addr, i);
+            }
+        }
+    }
+
+    let mut edges = vec![vec![]; advices.len()];
+    for (i, node) in advices.iter().enumerate() {
+        let mut seen = HashSet::new();
+        for addr in node.advice.inputs() {
+            if !seen.insert(addr) {
+                continue;
+            }
+            match writers.get(&addr) {
+                Some(&writer) => edges[writer].push(i),
+                None if external.contains(&addr) => (),
+                None => errors.push(GraphError::Unwritten { addr, advice: i, namespace: node.namespace.clone() }),
+            }
+        }
+    }
+
+    for cycle in cycles(&edges) {
+        let advices = cycle.into_iter().map(|i| (i, advices[i].namespace.clone())).collect();
+        errors.push(GraphError::Cycle { advices });
+    }
+
+    for addr in graph.outputs() {
+        if !writers.contains_key(addr) && !external.contains(addr) {
+            errors.push(GraphError::Unproduced { addr: *addr });
+        }
+    }
+
+    errors
+}
+
+/// Returns strongly connected components of the dependency graph which contain a cycle, using Tarjan's algorithm.
+fn cycles(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut next = 0;
+    let mut components = vec![];
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // (node, index of the next edge to visit)
+        let mut calls = vec![(root, 0)];
+        while let Some((v, i)) = calls.pop() {
+            if i == 0 {
+                index[v] = next;
+                low[v] = next;
+                next += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if i < edges[v].len() {
+                let w = edges[v][i];
+                calls.push((v, i + 1));
+                if index[w] == usize::MAX {
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut component = vec![];
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                if component.len() > 1 || edges[v].contains(&v) {
+                    component.sort();
+                    components.push(component);
+                }
+            }
+        }
+    }
+
+    components
+}
+
+/// Problem found in a circuit by validate_circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CircuitError<A> {
+    /// Value used in a constraint is not a signal.
+    ConstrainedNonSignal { addr: A, constraint: usize },
+    /// Value used in a lookup is not a signal.
+    LookedUpNonSignal { addr: A, lookup: usize },
+    /// Value read or written by an advice is neither a variable nor merged into one.
+    AdviceNonVariable { addr: A, advice: usize, namespace: String },
+}
+
+impl<A: Debug> Display for CircuitError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitError::ConstrainedNonSignal { addr, constraint } =>
+                write!(f, "constraint {constraint} uses {addr:?}, which is not a signal"),
+            CircuitError::LookedUpNonSignal { addr, lookup } =>
+                write!(f, "lookup {lookup} uses {addr:?}, which is not a signal"),
+            CircuitError::AdviceNonVariable { addr, advice, namespace } =>
+                write!(f, "{} uses {addr:?}, which is not a variable", AdviceRef(*advice, namespace)),
+        }
+    }
+}
+
+/// Checks that the circuit is consistent after transformations: constraints and lookups only use signals, and advices
+/// only use variables. Returns all problems found, empty if the circuit is valid.
+pub fn validate_circuit<C>(c: &C) -> Vec<CircuitError<C::RawAddr>>
+where
+    C: AdviceLog + ConstraintLog + Lookups + AliasFlag,
+{
+    let mut errors = vec![];
+    for (i, constraint) in c.constraints().iter().enumerate() {
+        for (_, sigs) in constraint.terms.iter() {
+            for addr in sigs.iter().filter(|addr| !(c.is_var(**addr) && c.is_sig(**addr))) {
+                errors.push(CircuitError::ConstrainedNonSignal { addr: *addr, constraint: i });
+            }
+        }
+    }
+    for (i, lookup) in c.lookups().iter().enumerate() {
+        for addr in lookup.sigs.iter().filter(|addr| !(c.is_var(**addr) && c.is_sig(**addr))) {
+            errors.push(CircuitError::LookedUpNonSignal { addr: *addr, lookup: i });
+        }
+    }
+    for (i, record) in c.advice_log().iter().enumerate() {
+        for addr in record.inputs.iter().chain(record.outputs.iter()) {
+            if !c.is_var(c.resolve(*addr)) {
+                errors.push(CircuitError::AdviceNonVariable { addr: *addr, advice: i, namespace: record.namespace.clone() });
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2curves::bn256::Fr;
+
+    use crate::{
+        circuit::{
+            BuildMode, ConstantFlag, Constraint, Inputs, Lookup, Namespaces, Signals, ToRawAddr, VariableFlag, Variables,
+        },
+        gadgets::traits::bigint_arith::range_table,
+        middleend::compiler::compile,
+        test_utils::{TestCircuit, TestStorage},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| x)[0];
+        c.in_namespace("twice", |c| c.advise_into(&[a], &[b], |x| x));
+        let u = c.alloc_sig::<Fr>();
+        c.advise_sigs(&[u], 1, |x| x);
+
+        let mut s = TestStorage::default();
+        let (graph, mapping) = compile(&c, &mut s);
+        let (b, u) = (mapping[&b.to_raw_addr()], mapping[&u.to_raw_addr()]);
+        assert_eq!(validate(&graph), vec![
+            GraphError::MultipleWriters { addr: b, advice: 1, namespace: "twice".to_string(), first: 0 },
+            GraphError::Unwritten { addr: u, advice: 2, namespace: "".to_string() },
+            GraphError::Unproduced { addr: u },
+        ]);
+
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_sig::<Fr>();
+        let b = c.advise_sigs(&[a], 1, |x| x)[0];
+        c.in_namespace("loop", |c| c.advise_into(&[b], &[a], |x| x));
+        let (graph, _) = compile(&c, &mut TestStorage::default());
+        assert_eq!(validate(&graph), vec![
+            GraphError::Cycle { advices: vec![(0, "".to_string()), (1, "loop".to_string())] },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_constants() {
+        // k is fixed by the circuit, m is computed by an advice.
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let k = c._alloc_sig_dependent::<Fr>();
+        c._set_const_flag(k.to_raw_addr(), true);
+        let m = c.advise_sigs(&[], 1, |_| vec![Fr::one()])[0];
+        c._set_const_flag(m.to_raw_addr(), true);
+        c.advise_sigs(&[a, k, m], 1, |x| vec![x[0] + x[1] + x[2]]);
+
+        let (graph, mapping) = compile(&c, &mut TestStorage::default());
+        assert_eq!(graph.constants(), &vec![mapping[&k.to_raw_addr()]]);
+        assert!(validate(&graph).is_empty());
+
+        c.advise_into(&[a], &[k], |x| x);
+        let (graph, _) = compile(&c, &mut TestStorage::default());
+        assert!(graph.constants().is_empty());
+        assert!(validate(&graph).is_empty());
+    }
+
+    #[test]
+    fn test_validate_external() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        assert!(validate(&compile(&c, &mut TestStorage::default()).0).is_empty());
+
+        let a = c.alloc_input::<Fr>();
+        // Reading a value twice is fine, writing an input is not.
+        let b = c.advise_sigs(&[a, a], 1, |x| vec![x[0] * x[1]])[0];
+        c.in_namespace("overwrite", |c| c.advise_into(&[b], &[a], |x| x));
+        let (graph, mapping) = compile(&c, &mut TestStorage::default());
+        assert_eq!(validate(&graph), vec![
+            GraphError::WritesExternal { addr: mapping[&a.to_raw_addr()], advice: 1, namespace: "overwrite".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_validate_self_cycle() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let u = c.alloc_sig::<Fr>();
+        c.advise_into(&[u], &[u], |x| x);
+        let (graph, _) = compile(&c, &mut TestStorage::default());
+        let errors = validate(&graph);
+        assert_eq!(errors, vec![GraphError::Cycle { advices: vec![(0, "".to_string())] }]);
+        assert_eq!(errors[0].to_string(), "cycle between advices: [advice 0]");
+    }
+
+    #[test]
+    fn test_validate_circuit() {
+        let mut c = TestCircuit::new(BuildMode::Full);
+        let a = c.alloc_input::<Fr>();
+        let v = c.alloc_var::<Fr>().to_raw_addr();
+        c.constraints_mut().push(Constraint { terms: vec![(Fr::one(), vec![a.to_raw_addr(), v])], namespace: String::new() });
+        let table = range_table(&mut c, 4);
+        c.lookups_mut().push(Lookup { table, sigs: vec![v], namespace: String::new() });
+        let b = c.in_namespace("dead", |c| c.advise_sigs(&[a], 1, |x| x)[0]);
+        c._set_var_flag(b.to_raw_addr(), false);
+
+        let errors = validate_circuit(&c);
+        assert_eq!(errors, vec![
+            CircuitError::ConstrainedNonSignal { addr: v, constraint: 0 },
+            CircuitError::LookedUpNonSignal { addr: v, lookup: 0 },
+            CircuitError::AdviceNonVariable { addr: b.to_raw_addr(), advice: 0, namespace: "dead".to_string() },
+        ]);
+        assert_eq!(errors[2].to_string(), format!("advice 0 in dead uses {}, which is not a variable", b.to_raw_addr()));
+
+        // Values merged into a variable are used through it.
+        let d = c.advise_sigs(&[a], 1, |x| x)[0];
+        c._set_alias(b.to_raw_addr(), Some(d.to_raw_addr()));
+        assert_eq!(validate_circuit(&c).len(), 2);
+    }
+}
@@ -0,0 +1,530 @@
+//! Circuit and storage used by tests throughout the crate.
+
+use std::any::TypeId;
+
+use ff::Field;
+use halo2curves::bn256::Fr;
+use num_bigint::BigUint;
+
+use crate::{
+    backend::{
+        api::RTGraph,
+        storage::{RawAllocator, ReaderOf, Storage, TypedAddr, TypedStorage, WriterOf},
+    },
+    circuit::{
+        AdviceLog, AdviceRecord, Advices, AliasFlag, BuildMode, BuildModes, ChallengeDependencyFlag, ChallengeFlag,
+        Circuit, CommitmentGroups, ConstantFlag, Constraint, ConstraintLog, Conversion, FuncId, HasSigtype,
+        HasVartype, InputFlag, Lookup, Lookups, Namespaces, PrimarySignalFlag, PublicFlag, RangeBound, SVStruct, Sig,
+        SignalFlag, Signals, Snapshots, Table, VariableFlag, _Into,
+    },
+    gadgets::{
+        cost::CostEstimate,
+        impls::{poseidon::DefaultPoseidon, rangecheck::LookupRangecheck},
+        traits::{
+            atoms::{LinearCombinationCostImpl, LinearCombinationImpl, Pow5CostImpl, Pow5Impl},
+            bigint_arith::RangecheckConfig,
+            nonzeros::{NonzerosCostImpl, NonzerosImpl},
+            poseidon_permutation::{MdsChoice, PoseidonConfig},
+        },
+    },
+    impl_gadgets,
+    middleend::{compiler::AddrMapping, degree::DegreeConfig},
+};
+
+pub(crate) type TSig = Sig<TestCircuit, Fr>;
+
+#[derive(Clone, Default)]
+struct AddrInfo {
+    var: bool,
+    sig: bool,
+    primary: bool,
+    constant: bool,
+    input: bool,
+    public: bool,
+    alias: Option<usize>,
+    bound: Option<BigUint>,
+    challenge_after: Option<usize>,
+    challenge_dependency: Option<usize>,
+    group: Option<usize>,
+}
+
+pub(crate) struct TestCircuit {
+    types: Vec<TypeId>,
+    addrs: Vec<AddrInfo>,
+    advices: Vec<AdviceRecord<Self, TestStorage>>,
+    constraints: Vec<Constraint<Self>>,
+    tables: Vec<Table<Fr>>,
+    lookups: Vec<Lookup<Self>>,
+    pub(crate) group: usize,
+    namespace: Vec<String>,
+    mode: BuildMode,
+    pub(crate) max_degree: usize,
+    pub(crate) poseidon_width: usize,
+    pub(crate) poseidon_full_rounds: usize,
+    pub(crate) poseidon_partial_rounds: usize,
+    pub(crate) poseidon_mds: MdsChoice,
+    pub(crate) rangecheck_base: usize,
+    pub(crate) lookup_table_size: usize,
+}
+
+impl TestCircuit {
+    pub(crate) fn new(mode: BuildMode) -> Self {
+        TestCircuit {
+            types: vec![],
+            addrs: vec![],
+            advices: vec![],
+            constraints: vec![],
+            tables: vec![],
+            lookups: vec![],
+            group: 0,
+            namespace: vec![],
+            mode,
+            max_degree: 2,
+            poseidon_width: 3,
+            poseidon_full_rounds: 2,
+            poseidon_partial_rounds: 0,
+            poseidon_mds: MdsChoice::Cauchy,
+            rangecheck_base: 256,
+            lookup_table_size: 256,
+        }
+    }
+
+    /// Advises primary signals from signals, with a closure over field elements.
+    #[track_caller]
+    pub(crate) fn advise_sigs(&mut self, inputs: &[TSig], num_outputs: usize, f: impl Fn(Vec<Fr>) -> Vec<Fr> + 'static) -> Vec<TSig> {
+        let outputs: Vec<TSig> = (0..num_outputs).map(|_| self.alloc_sig()).collect();
+        self.advise_into(inputs, &outputs, f);
+        outputs
+    }
+
+    /// Advises signals which are not committed, i.e. helper values of gadgets.
+    #[track_caller]
+    pub(crate) fn advise_dependent(&mut self, inputs: &[TSig], num_outputs: usize, f: impl Fn(Vec<Fr>) -> Vec<Fr> + 'static) -> Vec<TSig> {
+        let outputs: Vec<TSig> = (0..num_outputs).map(|_| self._alloc_sig_dependent()).collect();
+        self.advise_into(inputs, &outputs, f);
+        outputs
+    }
+
+    /// Advises already allocated signals. Unlike advise_sigs, allows to write a value twice.
+    #[track_caller]
+    pub(crate) fn advise_into(&mut self, inputs: &[TSig], outputs: &[TSig], f: impl Fn(Vec<Fr>) -> Vec<Fr> + 'static) {
+        self.advise_to_unassigned(f, &inputs.to_vec(), &outputs.to_vec());
+    }
+}
+
+impl Conversion<Fr, Fr> for TestCircuit {
+    fn convert(value: Fr) -> Fr {
+        value
+    }
+}
+
+impl DegreeConfig for TestCircuit {
+    fn max_degree(&self) -> usize {
+        self.max_degree
+    }
+}
+
+impl PoseidonConfig for TestCircuit {
+    fn poseidon_width(&self) -> usize {
+        self.poseidon_width
+    }
+
+    fn poseidon_full_rounds(&self) -> usize {
+        self.poseidon_full_rounds
+    }
+
+    fn poseidon_partial_rounds(&self) -> usize {
+        self.poseidon_partial_rounds
+    }
+
+    fn poseidon_mds(&self) -> MdsChoice {
+        self.poseidon_mds
+    }
+}
+
+impl RangecheckConfig for TestCircuit {
+    fn rangecheck_primitive_base(&self) -> usize {
+        self.rangecheck_base
+    }
+
+    fn lookup_table_size(&self) -> usize {
+        self.lookup_table_size
+    }
+}
+
+impl HasVartype<Fr> for TestCircuit {}
+impl HasSigtype<Fr> for TestCircuit {}
+
+impl Circuit for TestCircuit {
+    type F = Fr;
+    type RawAddr = usize;
+    type Config = Self;
+
+    fn config(&self) -> &Self {
+        self
+    }
+
+    fn raw_addrs(&self) -> Vec<usize> {
+        (0..self.addrs.len()).collect()
+    }
+
+    fn inner_type(&self, addr: usize) -> TypeId {
+        self.types[addr]
+    }
+
+    fn _alloc_raw<T: 'static>(&mut self) -> usize where Self: HasVartype<T> {
+        self.types.push(TypeId::of::<T>());
+        self.addrs.push(AddrInfo::default());
+        self.addrs.len() - 1
+    }
+}
+
+impl VariableFlag for TestCircuit {
+    fn is_var(&self, addr: usize) -> bool {
+        self.addrs[addr].var
+    }
+
+    fn _set_var_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].var = value
+    }
+}
+
+impl SignalFlag for TestCircuit {
+    fn is_sig(&self, addr: usize) -> bool {
+        self.addrs[addr].sig
+    }
+
+    fn _set_sig_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].sig = value
+    }
+}
+
+impl PrimarySignalFlag for TestCircuit {
+    fn is_primary(&self, addr: usize) -> bool {
+        self.addrs[addr].primary
+    }
+
+    fn _set_primary_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].primary = value;
+        self.addrs[addr].group = if value { Some(self.group) } else { None };
+    }
+}
+
+impl ConstantFlag for TestCircuit {
+    fn is_const(&self, addr: usize) -> bool {
+        self.addrs[addr].constant
+    }
+
+    fn _set_const_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].constant = value
+    }
+}
+
+impl InputFlag for TestCircuit {
+    fn is_input(&self, addr: usize) -> bool {
+        self.addrs[addr].input
+    }
+
+    fn _set_input_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].input = value
+    }
+}
+
+impl PublicFlag for TestCircuit {
+    fn is_public(&self, addr: usize) -> bool {
+        self.addrs[addr].public
+    }
+
+    fn _set_public_flag(&mut self, addr: usize, value: bool) {
+        self.addrs[addr].public = value
+    }
+}
+
+impl RangeBound for TestCircuit {
+    fn bound(&self, addr: usize) -> Option<BigUint> {
+        self.addrs[addr].bound.clone()
+    }
+
+    fn _set_bound(&mut self, addr: usize, value: Option<&BigUint>) {
+        self.addrs[addr].bound = value.cloned()
+    }
+}
+
+impl AliasFlag for TestCircuit {
+    fn alias(&self, addr: usize) -> Option<usize> {
+        self.addrs[addr].alias
+    }
+
+    fn _set_alias(&mut self, addr: usize, value: Option<usize>) {
+        self.addrs[addr].alias = value
+    }
+}
+
+impl ChallengeFlag for TestCircuit {
+    fn challenge_after(&self, addr: usize) -> Option<usize> {
+        self.addrs[addr].challenge_after
+    }
+
+    fn _set_challenge_after(&mut self, addr: usize, value: Option<usize>) {
+        self.addrs[addr].challenge_after = value
+    }
+}
+
+impl ChallengeDependencyFlag for TestCircuit {
+    fn challenge_dependency(&self, addr: usize) -> Option<usize> {
+        self.addrs[addr].challenge_dependency
+    }
+
+    fn _set_challenge_dependency(&mut self, addr: usize, value: Option<usize>) {
+        self.addrs[addr].challenge_dependency = value
+    }
+}
+
+impl CommitmentGroups for TestCircuit {
+    fn current_group(&self) -> usize {
+        self.group
+    }
+
+    fn is_sealed(&self, group: usize) -> bool {
+        group < self.group
+    }
+
+    fn group(&self, addr: usize) -> Option<usize> {
+        self.addrs[addr].group
+    }
+
+    fn _set_group(&mut self, addr: usize, value: Option<usize>) {
+        self.addrs[addr].group = value
+    }
+}
+
+impl Namespaces for TestCircuit {
+    fn namespace(&self) -> String {
+        self.namespace.join("/")
+    }
+
+    fn push_namespace(&mut self, name: &str) {
+        self.namespace.push(name.to_string())
+    }
+
+    fn pop_namespace(&mut self) {
+        self.namespace.pop().expect("already at top level");
+    }
+}
+
+impl Advices for TestCircuit {
+    #[track_caller]
+    fn advise_to_unassigned<I, O, F>(&mut self, f: F, input: &I, output: &O)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
+        self._log_advice(input, output, f)
+    }
+
+    #[track_caller]
+    fn advise_to_unassigned_with_params<I, O, F>(&mut self, f: F, input: &I, output: &O, params: Vec<String>)
+    where
+        I: SVStruct<Self> + Clone + 'static,
+        O: SVStruct<Self> + Clone + 'static,
+        F: Fn(I::FStruct) -> O::FStruct + 'static,
+    {
+        self._log_advice_with_id(input, output, f, Some(FuncId::of::<F>(params)))
+    }
+}
+
+impl BuildModes for TestCircuit {
+    fn build_mode(&self) -> BuildMode {
+        self.mode
+    }
+}
+
+impl AdviceLog for TestCircuit {
+    type Storage = TestStorage;
+
+    fn advice_log(&self) -> &Vec<AdviceRecord<Self, TestStorage>> {
+        &self.advices
+    }
+
+    fn advice_log_mut(&mut self) -> &mut Vec<AdviceRecord<Self, TestStorage>> {
+        &mut self.advices
+    }
+}
+
+impl ConstraintLog for TestCircuit {
+    fn constraints(&self) -> &Vec<Constraint<Self>> {
+        &self.constraints
+    }
+
+    fn constraints_mut(&mut self) -> &mut Vec<Constraint<Self>> {
+        &mut self.constraints
+    }
+}
+
+impl Lookups for TestCircuit {
+    fn tables(&self) -> &Vec<Table<Fr>> {
+        &self.tables
+    }
+
+    fn lookups(&self) -> &Vec<Lookup<Self>> {
+        &self.lookups
+    }
+
+    fn tables_mut(&mut self) -> &mut Vec<Table<Fr>> {
+        &mut self.tables
+    }
+
+    fn lookups_mut(&mut self) -> &mut Vec<Lookup<Self>> {
+        &mut self.lookups
+    }
+}
+
+/// State of TestCircuit apart from the logs.
+#[derive(Clone)]
+pub(crate) struct TestSnapshot {
+    types: Vec<TypeId>,
+    addrs: Vec<AddrInfo>,
+    group: usize,
+}
+
+impl Snapshots for TestCircuit {
+    type Snapshot = TestSnapshot;
+
+    fn snapshot(&self) -> TestSnapshot {
+        TestSnapshot { types: self.types.clone(), addrs: self.addrs.clone(), group: self.group }
+    }
+
+    fn restore(&mut self, snapshot: TestSnapshot) {
+        (self.types, self.addrs, self.group) = (snapshot.types, snapshot.addrs, snapshot.group);
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TestStorage {
+    pub(crate) data: Vec<Option<Fr>>,
+}
+
+impl Storage for TestStorage {
+    type RawAddr = usize;
+
+    fn to_raw<T>(ta: &TypedAddr<Self, T>) -> usize {
+        ta.addr
+    }
+}
+
+impl RawAllocator for TestStorage {
+    fn allocate_raw(&mut self, _ty: TypeId) -> usize {
+        self.data.push(None);
+        self.data.len() - 1
+    }
+}
+
+impl TypedStorage for TestStorage {
+    fn type_of(&self, _addr: &usize) -> TypeId {
+        TypeId::of::<Fr>()
+    }
+}
+
+impl ReaderOf<Fr> for TestStorage {
+    fn get(&self, addr: &usize) -> &Fr {
+        self.data[*addr].as_ref().expect("value is not computed")
+    }
+}
+
+impl WriterOf<Fr> for TestStorage {
+    fn put(&mut self, addr: &usize, val: Fr) {
+        assert!(self.data[*addr].is_none());
+        self.data[*addr] = Some(val);
+    }
+}
+
+/// Executes advices in order of recording.
+pub(crate) fn execute(graph: &RTGraph<TestStorage>, s: &mut TestStorage) {
+    for node in graph.advices().iter() {
+        node.advice.call(s);
+    }
+}
+
+/// Checks that all constraints of the circuit are satisfied by the computed values.
+pub(crate) fn check_constraints(c: &TestCircuit, s: &TestStorage, mapping: &AddrMapping<TestCircuit, TestStorage>) {
+    for constraint in c.constraints().iter() {
+        let value: Fr = constraint.terms.iter()
+            .map(|(coeff, sigs)| sigs.iter().fold(*coeff, |acc, sig| acc * s.get(&mapping[sig])))
+            .sum();
+        assert_eq!(value, Fr::zero());
+    }
+}
+
+/// Squares the signal with a recorded advice and a constraint.
+pub(crate) fn square(c: &mut TestCircuit, a: TSig) -> TSig {
+    let b = c.advise_sigs(&[a], 1, |x| vec![x[0] * x[0]])[0];
+    c.constrain(Constraint::new().term(Fr::one(), &[b._into()]).term(-Fr::one(), &[a._into(), a._into()]));
+    b
+}
+
+/// Advises a * b and constrains it.
+pub(crate) fn mul(c: &mut TestCircuit, a: TSig, b: TSig) -> TSig {
+    let t: TSig = c.advise(|x: Vec<Fr>| x[0] * x[1], &vec![a, b]);
+    c.constrain(Constraint::new().term(Fr::one(), &[t._into()]).term(-Fr::one(), &[a._into(), b._into()]));
+    t
+}
+
+/// Fifth power by three multiplications.
+pub(crate) struct MulPow5;
+
+impl Pow5Impl<TestCircuit> for MulPow5 {
+    fn pow5(c: &mut TestCircuit, x: TSig) -> TSig {
+        let x2 = mul(c, x, x);
+        let x4 = mul(c, x2, x2);
+        mul(c, x4, x)
+    }
+}
+
+impl Pow5CostImpl<TestCircuit> for MulPow5 {
+    fn pow5_cost(_c: &TestCircuit) -> CostEstimate {
+        CostEstimate { signals: 3, constraints: 3, advices: 3, lookups: 0 }
+    }
+}
+
+/// Linear combination by a single advice and constraint.
+pub(crate) struct AdvisedLc;
+
+impl LinearCombinationImpl<TestCircuit> for AdvisedLc {
+    fn lc(c: &mut TestCircuit, coeffs: Vec<Fr>, sigs: Vec<TSig>) -> TSig {
+        let k = coeffs.clone();
+        let params = coeffs.iter().map(|coeff| format!("{coeff:?}")).collect();
+        let t: TSig = c.advise_with_params(move |x: Vec<Fr>| x.iter().zip(k.iter()).map(|(x, k)| *x * k).sum(), &sigs, params);
+        let mut constraint = Constraint::new().term(Fr::one(), &[t._into()]);
+        for (coeff, sig) in coeffs.into_iter().zip(sigs) {
+            constraint = constraint.term(-coeff, &[sig._into()]);
+        }
+        c.constrain(constraint);
+        t
+    }
+}
+
+impl LinearCombinationCostImpl<TestCircuit> for AdvisedLc {
+    fn lc_cost(_c: &TestCircuit, _len: usize) -> CostEstimate {
+        CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 }
+    }
+}
+
+/// Nonzero check by an advised inverse.
+pub(crate) struct InverseNonzeros;
+
+impl NonzerosImpl<TestCircuit> for InverseNonzeros {
+    fn enforce_nonzero(c: &mut TestCircuit, x: TSig) {
+        let inv: TSig = c.advise(|x: Fr| x.invert().unwrap_or(Fr::zero()), &x);
+        c.constrain(Constraint::new().term(Fr::one(), &[x._into(), inv._into()]).term(-Fr::one(), &[]));
+    }
+}
+
+impl NonzerosCostImpl<TestCircuit> for InverseNonzeros {
+    fn enforce_nonzero_cost(_c: &TestCircuit) -> CostEstimate {
+        CostEstimate { signals: 1, constraints: 1, advices: 1, lookups: 0 }
+    }
+}
+
+impl_gadgets!(TestCircuit: Pow5 = MulPow5, Lc = AdvisedLc, Poseidon = DefaultPoseidon, Rangecheck = LookupRangecheck,
+    Nonzeros = InverseNonzeros);
@@ -0,0 +1,6 @@
+struct Circuit;
+struct LcImpl;
+
+zk_frontend::impl_gadgets!(Circuit: LinearCombination = LcImpl, Lc = LcImpl);
+
+fn main() {}